@@ -0,0 +1,486 @@
+//! Single and double elimination brackets.
+//!
+//! Rather than a fixed tree, entrants are grouped by how many matches they've
+//! lost, and each bracket round pairs them up within those groups. In double
+//! elimination, the group with no losses is the winners' bracket and the one
+//! with a loss is the losers' bracket. Once they each have one player left,
+//! those two meet in the grand final, which is played again if the player from
+//! the losers' bracket wins.
+
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum Elimination {
+    Single,
+    Double,
+}
+
+impl Elimination {
+    /// Matches a player can lose before they're knocked out
+    fn losses_allowed(self) -> usize {
+        match self {
+            Elimination::Single => 1,
+            Elimination::Double => 2,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Entrant {
+    pub user_name: String,
+    /// 1 is the top seed
+    pub seed: usize,
+    pub losses: usize,
+    /// The bracket round they were knocked out in
+    pub eliminated_in: Option<usize>,
+    /// Rounds they've sat out, so byes get spread around
+    pub byes: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BracketMatch {
+    pub players: [String; 2],
+    pub winner: Option<String>,
+    /// Throws that came out even and had to be played again
+    pub draws: usize,
+}
+
+impl BracketMatch {
+    fn has(&self, user_name: &str) -> bool {
+        self.players.iter().any(|p| p == user_name)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Bracket {
+    pub elimination: Elimination,
+    /// The bracket round being played, starting from 1
+    pub round: usize,
+    /// In seed order
+    pub entrants: Vec<Entrant>,
+    pub matches: Vec<BracketMatch>,
+    /// Entrants sitting this round out
+    pub byes: Vec<String>,
+}
+
+impl Bracket {
+    /// Seed the players at random and draw up the first round
+    pub fn new(elimination: Elimination, mut user_names: Vec<String>) -> Self {
+        user_names.shuffle(&mut thread_rng());
+
+        let mut bracket = Bracket {
+            elimination,
+            round: 0,
+            entrants: user_names
+                .into_iter()
+                .enumerate()
+                .map(|(i, user_name)| Entrant {
+                    user_name,
+                    seed: i + 1,
+                    losses: 0,
+                    eliminated_in: None,
+                    byes: 0,
+                })
+                .collect(),
+            matches: Vec::new(),
+            byes: Vec::new(),
+        };
+
+        bracket.next_round(|_| true);
+        bracket
+    }
+
+    fn alive(&self) -> impl Iterator<Item = &Entrant> {
+        let losses_allowed = self.elimination.losses_allowed();
+        self.entrants
+            .iter()
+            .filter(move |e| e.losses < losses_allowed)
+    }
+
+    /// The last entrant standing, once there is one
+    pub fn champion(&self) -> Option<&str> {
+        let mut alive = self.alive();
+
+        match (alive.next(), alive.next()) {
+            (Some(champion), None) => Some(champion.user_name.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Nobody is left to play anyone
+    pub fn finished(&self) -> bool {
+        self.alive().nth(1).is_none()
+    }
+
+    /// Matches that still need a winner
+    pub fn open_matches(&self) -> impl Iterator<Item = &BracketMatch> {
+        self.matches.iter().filter(|m| m.winner.is_none())
+    }
+
+    pub fn round_decided(&self) -> bool {
+        self.open_matches().next().is_none()
+    }
+
+    /// Draw up the next round. Anyone who can't play it, according to
+    /// `can_play`, is knocked out.
+    pub fn next_round<F: Fn(&str) -> bool>(&mut self, can_play: F) {
+        let losses_allowed = self.elimination.losses_allowed();
+
+        self.round += 1;
+        self.matches.clear();
+        self.byes.clear();
+
+        for entrant in self.entrants.iter_mut() {
+            if entrant.losses < losses_allowed && !can_play(&entrant.user_name) {
+                entrant.losses = losses_allowed;
+                entrant.eliminated_in = Some(self.round);
+            }
+        }
+
+        let alive: Vec<usize> = (0..self.entrants.len())
+            .filter(|&i| self.entrants[i].losses < losses_allowed)
+            .collect();
+
+        if alive.len() < 2 {
+            return;
+        }
+
+        // Last two standing meet no matter which side of the bracket they're on
+        if alive.len() == 2 {
+            self.schedule(alive[0], alive[1]);
+            return;
+        }
+
+        for losses in 0..losses_allowed {
+            let mut pool: Vec<usize> = alive
+                .iter()
+                .copied()
+                .filter(|&i| self.entrants[i].losses == losses)
+                .collect();
+
+            if pool.len() % 2 == 1 {
+                // Whoever has sat out the least gets the bye, top seeds first
+                let bye = *pool
+                    .iter()
+                    .min_by_key(|&&i| (self.entrants[i].byes, self.entrants[i].seed))
+                    .expect("pool is not empty");
+                pool.retain(|&i| i != bye);
+
+                self.entrants[bye].byes += 1;
+                self.byes.push(self.entrants[bye].user_name.clone());
+            }
+
+            // Top seeds play bottom seeds
+            let half = pool.len() / 2;
+            for k in 0..half {
+                self.schedule(pool[k], pool[pool.len() - 1 - k]);
+            }
+        }
+    }
+
+    fn schedule(&mut self, first: usize, second: usize) {
+        self.matches.push(BracketMatch {
+            players: [
+                self.entrants[first].user_name.clone(),
+                self.entrants[second].user_name.clone(),
+            ],
+            winner: None,
+            draws: 0,
+        });
+    }
+
+    fn open_match_mut(&mut self, a: &str, b: &str) -> Option<&mut BracketMatch> {
+        self.matches
+            .iter_mut()
+            .find(|m| m.winner.is_none() && m.has(a) && m.has(b))
+    }
+
+    pub fn record_win(&mut self, winner: &str, loser: &str) {
+        let round = self.round;
+        let losses_allowed = self.elimination.losses_allowed();
+
+        match self.open_match_mut(winner, loser) {
+            Some(m) => m.winner = Some(winner.to_string()),
+            None => return,
+        }
+
+        if let Some(entrant) = self.entrants.iter_mut().find(|e| e.user_name == loser) {
+            entrant.losses += 1;
+            if entrant.losses >= losses_allowed {
+                entrant.eliminated_in = Some(round);
+            }
+        }
+    }
+
+    pub fn record_draw(&mut self, a: &str, b: &str) {
+        if let Some(m) = self.open_match_mut(a, b) {
+            m.draws += 1;
+        }
+    }
+
+    /// Of two entrants, the one seeded higher
+    pub fn better_seed<'a>(&self, a: &'a str, b: &'a str) -> &'a str {
+        let seed = |user_name: &str| {
+            self.entrants
+                .iter()
+                .find(|e| e.user_name == user_name)
+                .map_or(usize::MAX, |e| e.seed)
+        };
+
+        if seed(b) < seed(a) {
+            b
+        } else {
+            a
+        }
+    }
+
+    /// Final placings, best first. Entrants still standing share first, then
+    /// everyone else by how long they lasted, sharing a place when they went
+    /// out in the same round.
+    pub fn placements(&self) -> Vec<(usize, &str)> {
+        let mut order: Vec<&Entrant> = self.entrants.iter().collect();
+        order.sort_by_key(|e| {
+            (
+                std::cmp::Reverse(e.eliminated_in.unwrap_or(usize::MAX)),
+                e.seed,
+            )
+        });
+
+        let mut placements: Vec<(usize, &str)> = Vec::with_capacity(order.len());
+        for (i, e) in order.iter().enumerate() {
+            let place = match i.checked_sub(1).map(|prev| order[prev]) {
+                Some(prev) if prev.eliminated_in == e.eliminated_in => placements[i - 1].0,
+                _ => i + 1,
+            };
+
+            placements.push((place, e.user_name.as_str()));
+        }
+
+        placements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bracket for `count` players, and their names from the top seed down
+    fn bracket(elimination: Elimination, count: usize) -> (Bracket, Vec<String>) {
+        let user_names = (1..=count).map(|i| format!("Player {}", i)).collect();
+        let bracket = Bracket::new(elimination, user_names);
+        let seeds = bracket
+            .entrants
+            .iter()
+            .map(|e| e.user_name.clone())
+            .collect();
+
+        (bracket, seeds)
+    }
+
+    fn matches(bracket: &Bracket) -> Vec<[&str; 2]> {
+        bracket
+            .matches
+            .iter()
+            .map(|m| [m.players[0].as_str(), m.players[1].as_str()])
+            .collect()
+    }
+
+    fn placements(bracket: &Bracket) -> Vec<(usize, String)> {
+        bracket
+            .placements()
+            .into_iter()
+            .map(|(place, user_name)| (place, user_name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn seeds_everyone_once() {
+        let (bracket, seeds) = bracket(Elimination::Single, 5);
+
+        let mut sorted = seeds.clone();
+        sorted.sort();
+        assert_eq!(
+            sorted,
+            (1..=5).map(|i| format!("Player {}", i)).collect::<Vec<_>>()
+        );
+
+        let seed_numbers: Vec<usize> = bracket.entrants.iter().map(|e| e.seed).collect();
+        assert_eq!(seed_numbers, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn gives_the_bye_to_the_top_seed() {
+        let (mut bracket, s) = bracket(Elimination::Single, 3);
+
+        assert_eq!(bracket.round, 1);
+        assert_eq!(bracket.byes, vec![s[0].clone()]);
+        assert_eq!(matches(&bracket), vec![[s[1].as_str(), s[2].as_str()]]);
+
+        bracket.record_win(&s[1], &s[2]);
+        assert!(bracket.round_decided());
+        assert!(!bracket.finished());
+
+        bracket.next_round(|_| true);
+        assert!(bracket.byes.is_empty());
+        assert_eq!(matches(&bracket), vec![[s[0].as_str(), s[1].as_str()]]);
+
+        bracket.record_win(&s[0], &s[1]);
+        assert!(bracket.finished());
+        assert_eq!(bracket.champion(), Some(s[0].as_str()));
+        assert_eq!(
+            placements(&bracket),
+            vec![(1, s[0].clone()), (2, s[1].clone()), (3, s[2].clone())]
+        );
+    }
+
+    #[test]
+    fn spreads_byes_around() {
+        let (mut bracket, s) = bracket(Elimination::Double, 3);
+        assert_eq!(bracket.byes, vec![s[0].clone()]);
+
+        // The top seed has had a bye, so the next one goes to whoever hasn't
+        bracket.record_win(&s[1], &s[2]);
+        bracket.next_round(|_| true);
+
+        assert_eq!(bracket.byes, vec![s[2].clone()]);
+        assert_eq!(matches(&bracket), vec![[s[0].as_str(), s[1].as_str()]]);
+    }
+
+    #[test]
+    fn replays_the_grand_final_when_the_losers_bracket_wins_it() {
+        let (mut bracket, s) = bracket(Elimination::Double, 4);
+
+        // Top seeds play bottom seeds
+        assert_eq!(
+            matches(&bracket),
+            vec![
+                [s[0].as_str(), s[3].as_str()],
+                [s[1].as_str(), s[2].as_str()]
+            ]
+        );
+        bracket.record_win(&s[0], &s[3]);
+        bracket.record_win(&s[1], &s[2]);
+
+        // Winners' and losers' brackets are drawn separately
+        bracket.next_round(|_| true);
+        assert_eq!(
+            matches(&bracket),
+            vec![
+                [s[0].as_str(), s[1].as_str()],
+                [s[2].as_str(), s[3].as_str()]
+            ]
+        );
+        bracket.record_win(&s[0], &s[1]);
+        bracket.record_win(&s[2], &s[3]);
+        assert_eq!(bracket.entrants[3].eliminated_in, Some(2));
+
+        // The winners' bracket is down to one, so they wait for the losers' final
+        bracket.next_round(|_| true);
+        assert_eq!(bracket.byes, vec![s[0].clone()]);
+        assert_eq!(matches(&bracket), vec![[s[1].as_str(), s[2].as_str()]]);
+        bracket.record_win(&s[1], &s[2]);
+
+        // Grand final, which the losers' bracket wins
+        bracket.next_round(|_| true);
+        assert_eq!(matches(&bracket), vec![[s[0].as_str(), s[1].as_str()]]);
+        bracket.record_win(&s[1], &s[0]);
+
+        assert!(!bracket.finished());
+        assert_eq!(bracket.champion(), None);
+
+        // Now both have lost once, so it's played again
+        bracket.next_round(|_| true);
+        assert_eq!(bracket.round, 5);
+        assert_eq!(matches(&bracket), vec![[s[0].as_str(), s[1].as_str()]]);
+        bracket.record_win(&s[1], &s[0]);
+
+        assert!(bracket.finished());
+        assert_eq!(bracket.champion(), Some(s[1].as_str()));
+        assert_eq!(
+            placements(&bracket),
+            vec![
+                (1, s[1].clone()),
+                (2, s[0].clone()),
+                (3, s[2].clone()),
+                (4, s[3].clone()),
+            ]
+        );
+    }
+
+    #[test]
+    fn knocks_out_players_who_cant_play() {
+        let (mut bracket, s) = bracket(Elimination::Double, 4);
+        bracket.record_win(&s[0], &s[3]);
+        bracket.record_win(&s[1], &s[2]);
+
+        // A player who's gone is out, however many losses they had left
+        let gone = s[0].clone();
+        bracket.next_round(|user_name| user_name != gone);
+
+        assert_eq!(bracket.entrants[0].losses, 2);
+        assert_eq!(bracket.entrants[0].eliminated_in, Some(2));
+        assert!(bracket.matches.iter().all(|m| !m.has(&s[0])));
+        assert_eq!(bracket.byes, vec![s[1].clone()]);
+        assert_eq!(matches(&bracket), vec![[s[2].as_str(), s[3].as_str()]]);
+    }
+
+    #[test]
+    fn finishes_when_only_one_can_play() {
+        let (mut bracket, s) = bracket(Elimination::Single, 2);
+
+        let stays = s[1].clone();
+        bracket.next_round(|user_name| user_name == stays);
+
+        assert!(bracket.matches.is_empty());
+        assert!(bracket.finished());
+        assert_eq!(bracket.champion(), Some(s[1].as_str()));
+    }
+
+    #[test]
+    fn keeps_drawn_matches_open() {
+        let (mut bracket, s) = bracket(Elimination::Single, 2);
+
+        bracket.record_draw(&s[1], &s[0]);
+        bracket.record_draw(&s[0], &s[1]);
+        assert_eq!(bracket.matches[0].draws, 2);
+        assert!(!bracket.round_decided());
+
+        // Only players in the same match can beat each other
+        bracket.record_win(&s[0], "Someone else");
+        assert!(!bracket.round_decided());
+
+        bracket.record_win(&s[0], &s[1]);
+        assert_eq!(bracket.matches[0].winner.as_deref(), Some(s[0].as_str()));
+        assert!(bracket.finished());
+    }
+
+    #[test]
+    fn shares_places_between_players_out_in_the_same_round() {
+        let (mut bracket, s) = bracket(Elimination::Single, 4);
+        bracket.record_win(&s[0], &s[3]);
+        bracket.record_win(&s[1], &s[2]);
+        bracket.next_round(|_| true);
+        bracket.record_win(&s[1], &s[0]);
+
+        // Shared places are listed by seed, and the place after them is skipped
+        assert_eq!(
+            placements(&bracket),
+            vec![
+                (1, s[1].clone()),
+                (2, s[0].clone()),
+                (3, s[2].clone()),
+                (3, s[3].clone()),
+            ]
+        );
+    }
+
+    #[test]
+    fn shares_first_while_the_bracket_is_unfinished() {
+        let (bracket, s) = bracket(Elimination::Single, 3);
+
+        assert!(placements(&bracket).iter().all(|(place, _)| *place == 1));
+        assert_eq!(bracket.better_seed(&s[2], &s[0]), s[0].as_str());
+        assert_eq!(bracket.better_seed(&s[1], "Someone else"), s[1].as_str());
+    }
+}
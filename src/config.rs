@@ -0,0 +1,424 @@
+//! Server settings, and where they come from.
+//!
+//! Every setting can be given on the command line, in the environment or in a
+//! TOML file named with `--config`. The command line and environment win over
+//! the file, and anything still unset gets a default. The result is checked
+//! once at startup, so a bad setting stops the server before it serves anyone.
+
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use structopt::StructOpt;
+
+use crate::irc::{self, IrcConfig};
+use crate::moderation::{BlockedWords, Moderator};
+use crate::room_code::{self, RoomCodeAllocator, RoomCodeError};
+
+pub const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:3030";
+pub const DEFAULT_STATIC_DIR: &str = "static";
+pub const DEFAULT_MAX_ROOMS: usize = 1000;
+pub const DEFAULT_MAX_PLAYERS_PER_ROOM: usize = 50;
+pub const DEFAULT_ROOM_IDLE_TIMEOUT_SECS: u64 = 30 * 60;
+pub const DEFAULT_ROOM_EXPIRY_WARNING_SECS: u64 = 2 * 60;
+pub const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 10;
+pub const DEFAULT_ROUND_TIME_LIMIT_SECS: u64 = 30;
+pub const DEFAULT_QUESTION_PACK_DIR: &str = "questions";
+
+/// Server settings, from the command line, the environment or a TOML config file.
+///
+/// Anything given on the command line or in the environment wins over the config file.
+#[derive(Debug, Default, StructOpt, Deserialize)]
+#[structopt(name = "untitled-stream-game")]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerOptions {
+    /// TOML file with any of the options below, using their long names in snake_case
+    #[structopt(long, env = "USG_CONFIG", parse(from_os_str))]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+
+    /// Address and port to serve the game on [default: 127.0.0.1:3030]
+    #[structopt(long, env = "USG_LISTEN_ADDR")]
+    pub server_listen_addr: Option<SocketAddr>,
+
+    /// Directory holding index.html and the rest of the client [default: static]
+    #[structopt(long, env = "USG_STATIC_DIR", parse(from_os_str))]
+    pub static_dir: Option<PathBuf>,
+
+    /// Most rooms that can be open at once [default: 1000]
+    #[structopt(long, env = "USG_MAX_ROOMS")]
+    pub max_rooms: Option<usize>,
+
+    /// Most players allowed in a single room [default: 50]
+    #[structopt(long, env = "USG_MAX_PLAYERS_PER_ROOM")]
+    pub max_players_per_room: Option<usize>,
+
+    /// Log filter, in the same format as RUST_LOG [default: RUST_LOG]
+    #[structopt(long, env = "USG_LOG_LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Number of characters in a room code [default: 4]
+    #[structopt(long, env = "USG_ROOM_CODE_LENGTH")]
+    pub room_code_length: Option<usize>,
+
+    /// Characters room codes are made from [default: A-Z without I and O]
+    #[structopt(long, env = "USG_ROOM_CODE_ALPHABET")]
+    pub room_code_alphabet: Option<String>,
+
+    /// Words that must never appear in a room code, replacing the built-in list
+    #[structopt(long, env = "USG_ROOM_CODE_BLOCKLIST", use_delimiter = true)]
+    pub room_code_blocklist: Option<Vec<String>>,
+
+    /// Seconds a room can sit idle before it is closed [default: 1800]
+    #[structopt(long, env = "USG_ROOM_IDLE_TIMEOUT")]
+    pub room_idle_timeout_secs: Option<u64>,
+
+    /// Seconds of warning a room gets before it expires [default: 120]
+    #[structopt(long, env = "USG_ROOM_EXPIRY_WARNING")]
+    pub room_expiry_warning_secs: Option<u64>,
+
+    /// Seconds players get to wrap up after the server is told to shut down [default: 10]
+    #[structopt(long, env = "USG_SHUTDOWN_GRACE")]
+    pub shutdown_grace_secs: Option<u64>,
+
+    /// Seconds players get to throw each round, 0 to wait for everyone [default: 30]
+    #[structopt(long, env = "USG_ROUND_TIME_LIMIT")]
+    pub round_time_limit_secs: Option<u64>,
+
+    /// Directory holding trivia question packs, as TOML or JSON files [default: questions]
+    #[structopt(long, env = "USG_QUESTION_PACK_DIR", parse(from_os_str))]
+    pub question_pack_dir: Option<PathBuf>,
+
+    /// Words player submissions can't use, replacing the room code blocklist
+    #[structopt(long, env = "USG_BLOCKED_WORDS", use_delimiter = true)]
+    pub blocked_words: Option<Vec<String>>,
+
+    /// IRC server to bridge chat from, as host:port. The bridge is off unless this is set.
+    #[structopt(long, env = "USG_IRC_SERVER")]
+    pub irc_server: Option<String>,
+
+    /// IRC channel viewers play from, needed with --irc-server
+    #[structopt(long, env = "USG_IRC_CHANNEL")]
+    pub irc_channel: Option<String>,
+
+    /// Nick the bridge goes by in chat [default: usg-bot]
+    #[structopt(long, env = "USG_IRC_NICK")]
+    pub irc_nick: Option<String>,
+
+    /// Server password, such as a Twitch "oauth:" token
+    #[structopt(long, env = "USG_IRC_PASSWORD")]
+    pub irc_password: Option<String>,
+
+    /// Nicks allowed to bind chat to a room [default: the channel name]
+    #[structopt(long, env = "USG_IRC_OPERATORS", use_delimiter = true)]
+    pub irc_operators: Option<Vec<String>>,
+}
+
+impl ServerOptions {
+    /// Fill in anything not set here from `other`
+    fn or(self, other: ServerOptions) -> ServerOptions {
+        ServerOptions {
+            config: self.config.or(other.config),
+            server_listen_addr: self.server_listen_addr.or(other.server_listen_addr),
+            static_dir: self.static_dir.or(other.static_dir),
+            max_rooms: self.max_rooms.or(other.max_rooms),
+            max_players_per_room: self.max_players_per_room.or(other.max_players_per_room),
+            log_level: self.log_level.or(other.log_level),
+            room_code_length: self.room_code_length.or(other.room_code_length),
+            room_code_alphabet: self.room_code_alphabet.or(other.room_code_alphabet),
+            room_code_blocklist: self.room_code_blocklist.or(other.room_code_blocklist),
+            room_idle_timeout_secs: self.room_idle_timeout_secs.or(other.room_idle_timeout_secs),
+            room_expiry_warning_secs: self
+                .room_expiry_warning_secs
+                .or(other.room_expiry_warning_secs),
+            shutdown_grace_secs: self.shutdown_grace_secs.or(other.shutdown_grace_secs),
+            round_time_limit_secs: self.round_time_limit_secs.or(other.round_time_limit_secs),
+            question_pack_dir: self.question_pack_dir.or(other.question_pack_dir),
+            blocked_words: self.blocked_words.or(other.blocked_words),
+            irc_server: self.irc_server.or(other.irc_server),
+            irc_channel: self.irc_channel.or(other.irc_channel),
+            irc_nick: self.irc_nick.or(other.irc_nick),
+            irc_password: self.irc_password.or(other.irc_password),
+            irc_operators: self.irc_operators.or(other.irc_operators),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    RoomCode(RoomCodeError),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "Unable to read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "Unable to parse {}: {}", path.display(), e),
+            ConfigError::RoomCode(e) => write!(f, "Bad room code settings: {}", e),
+            ConfigError::Invalid(e) => write!(f, "Bad config: {}", e),
+        }
+    }
+}
+
+/// Settings the server runs with, after defaults have been filled in
+#[derive(Debug)]
+pub struct ServerConfig {
+    pub listen_addr: SocketAddr,
+    pub static_dir: PathBuf,
+    pub max_rooms: usize,
+    pub max_players_per_room: usize,
+    pub log_level: Option<String>,
+    pub room_codes: RoomCodeAllocator,
+    pub room_idle_timeout: Duration,
+    pub room_expiry_warning: Duration,
+    pub shutdown_grace: Duration,
+    /// Time players get to throw each round, unless rounds wait for everyone
+    pub round_time_limit: Option<Duration>,
+    pub question_pack_dir: PathBuf,
+    /// Everything player submissions have to get past, in order
+    pub moderators: Vec<Arc<dyn Moderator>>,
+    /// Set when chat should be bridged in from IRC
+    pub irc: Option<IrcConfig>,
+}
+
+impl ServerConfig {
+    /// Read the command line and environment, plus the config file if one was given
+    pub fn load() -> Result<ServerConfig, ConfigError> {
+        let cli_opts = ServerOptions::from_args();
+
+        let file_opts = match cli_opts.config.as_ref() {
+            Some(path) => {
+                let contents =
+                    fs::read_to_string(path).map_err(|e| ConfigError::Read(path.clone(), e))?;
+                toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.clone(), e))?
+            }
+            None => ServerOptions::default(),
+        };
+
+        ServerConfig::from_options(cli_opts.or(file_opts))
+    }
+
+    pub fn from_options(opts: ServerOptions) -> Result<ServerConfig, ConfigError> {
+        let listen_addr = match opts.server_listen_addr {
+            Some(addr) => addr,
+            None => DEFAULT_LISTEN_ADDR
+                .parse()
+                .expect("Default listen address is valid"),
+        };
+
+        let max_rooms = opts.max_rooms.unwrap_or(DEFAULT_MAX_ROOMS);
+        let max_players_per_room = opts
+            .max_players_per_room
+            .unwrap_or(DEFAULT_MAX_PLAYERS_PER_ROOM);
+
+        if max_rooms == 0 || max_players_per_room == 0 {
+            return Err(ConfigError::Invalid(
+                "max_rooms and max_players_per_room must be at least 1".to_string(),
+            ));
+        }
+
+        let room_idle_timeout = Duration::from_secs(
+            opts.room_idle_timeout_secs
+                .unwrap_or(DEFAULT_ROOM_IDLE_TIMEOUT_SECS),
+        );
+        let room_expiry_warning = Duration::from_secs(
+            opts.room_expiry_warning_secs
+                .unwrap_or(DEFAULT_ROOM_EXPIRY_WARNING_SECS),
+        );
+
+        if room_expiry_warning >= room_idle_timeout {
+            return Err(ConfigError::Invalid(
+                "room_expiry_warning_secs must be shorter than room_idle_timeout_secs".to_string(),
+            ));
+        }
+
+        let room_codes = RoomCodeAllocator::new(
+            opts.room_code_length.unwrap_or(room_code::DEFAULT_LENGTH),
+            opts.room_code_alphabet
+                .as_deref()
+                .unwrap_or(room_code::DEFAULT_ALPHABET),
+            opts.room_code_blocklist.unwrap_or_else(|| {
+                room_code::DEFAULT_BLOCKLIST
+                    .iter()
+                    .map(|w| w.to_string())
+                    .collect()
+            }),
+        )
+        .map_err(ConfigError::RoomCode)?;
+
+        let irc = match opts.irc_server {
+            Some(server) => {
+                let channel = opts.irc_channel.ok_or_else(|| {
+                    ConfigError::Invalid("irc_channel is needed with irc_server".to_string())
+                })?;
+
+                Some(IrcConfig::new(
+                    server,
+                    &channel,
+                    opts.irc_nick
+                        .unwrap_or_else(|| irc::DEFAULT_NICK.to_string()),
+                    opts.irc_password,
+                    opts.irc_operators,
+                ))
+            }
+            None => None,
+        };
+
+        Ok(ServerConfig {
+            listen_addr,
+            static_dir: opts
+                .static_dir
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_STATIC_DIR)),
+            max_rooms,
+            max_players_per_room,
+            log_level: opts.log_level,
+            room_codes,
+            room_idle_timeout,
+            room_expiry_warning,
+            shutdown_grace: Duration::from_secs(
+                opts.shutdown_grace_secs
+                    .unwrap_or(DEFAULT_SHUTDOWN_GRACE_SECS),
+            ),
+            round_time_limit: match opts
+                .round_time_limit_secs
+                .unwrap_or(DEFAULT_ROUND_TIME_LIMIT_SECS)
+            {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            question_pack_dir: opts
+                .question_pack_dir
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_QUESTION_PACK_DIR)),
+            moderators: vec![Arc::new(BlockedWords::new(
+                opts.blocked_words.unwrap_or_else(|| {
+                    room_code::DEFAULT_BLOCKLIST
+                        .iter()
+                        .map(|w| w.to_string())
+                        .collect()
+                }),
+            ))],
+            irc,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::iter;
+
+    fn cli(args: &[&str]) -> ServerOptions {
+        ServerOptions::from_iter(iter::once("untitled-stream-game").chain(args.iter().copied()))
+    }
+
+    fn file(contents: &str) -> ServerOptions {
+        toml::from_str(contents).unwrap()
+    }
+
+    fn invalid(opts: ServerOptions) -> String {
+        match ServerConfig::from_options(opts) {
+            Err(ConfigError::Invalid(e)) => e,
+            other => panic!("Expected a bad config, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn command_line_wins_over_the_file() {
+        let opts = cli(&["--max-rooms", "5", "--irc-operators", "alice,bob"]).or(file(
+            r#"
+max_rooms = 10
+max_players_per_room = 8
+irc_operators = ["carol"]
+"#,
+        ));
+
+        assert_eq!(opts.max_rooms, Some(5));
+        assert_eq!(opts.max_players_per_room, Some(8));
+        assert_eq!(
+            opts.irc_operators,
+            Some(vec!["alice".to_string(), "bob".to_string()])
+        );
+        assert_eq!(opts.room_code_length, None);
+    }
+
+    #[test]
+    fn rejects_unknown_settings_in_the_file() {
+        assert!(toml::from_str::<ServerOptions>("max_room = 10").is_err());
+    }
+
+    #[test]
+    fn fills_in_defaults() {
+        let config = ServerConfig::from_options(ServerOptions::default()).unwrap();
+
+        assert_eq!(config.listen_addr, DEFAULT_LISTEN_ADDR.parse().unwrap());
+        assert_eq!(config.max_rooms, DEFAULT_MAX_ROOMS);
+        assert_eq!(config.max_players_per_room, DEFAULT_MAX_PLAYERS_PER_ROOM);
+        assert_eq!(
+            config.round_time_limit,
+            Some(Duration::from_secs(DEFAULT_ROUND_TIME_LIMIT_SECS))
+        );
+        assert_eq!(
+            config.question_pack_dir,
+            PathBuf::from(DEFAULT_QUESTION_PACK_DIR)
+        );
+        assert!(config.irc.is_none());
+    }
+
+    #[test]
+    fn turns_off_the_round_clock_with_zero() {
+        let config = ServerConfig::from_options(file("round_time_limit_secs = 0")).unwrap();
+        assert_eq!(config.round_time_limit, None);
+    }
+
+    #[test]
+    fn needs_room_for_rooms_and_players() {
+        invalid(file("max_rooms = 0"));
+        invalid(file("max_players_per_room = 0"));
+    }
+
+    #[test]
+    fn warns_before_rooms_expire() {
+        invalid(file(
+            "room_idle_timeout_secs = 60\nroom_expiry_warning_secs = 60",
+        ));
+        assert!(ServerConfig::from_options(file(
+            "room_idle_timeout_secs = 60\nroom_expiry_warning_secs = 59"
+        ))
+        .is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_room_codes() {
+        assert!(matches!(
+            ServerConfig::from_options(file("room_code_length = 0")),
+            Err(ConfigError::RoomCode(_))
+        ));
+    }
+
+    #[test]
+    fn needs_a_channel_for_irc() {
+        let e = invalid(file(r#"irc_server = "irc.chat.twitch.tv:6667""#));
+        assert!(e.contains("irc_channel"), "{}", e);
+
+        let config = ServerConfig::from_options(file(
+            r#"
+irc_server = "irc.chat.twitch.tv:6667"
+irc_channel = "Streamer"
+"#,
+        ))
+        .unwrap();
+        let irc = config.irc.unwrap();
+
+        assert_eq!(irc.channel, "#streamer");
+        assert_eq!(irc.nick, irc::DEFAULT_NICK);
+        assert_eq!(irc.operators, vec!["streamer"]);
+    }
+}
@@ -0,0 +1,144 @@
+//! The games a room can play.
+//!
+//! A room looks after the party: who's in it, their scores and rounds played,
+//! the connections, and telling everyone what happened. What happens in a
+//! match is up to the room's `Game`. Players reach it by sending a
+//! `GameAction`, and it answers the room with `GameEvent`s. Adding a game is a
+//! new `Game` plus a variant in each of the enums here.
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::ServerConfig;
+use crate::prompt::{PromptAction, PromptEvent, PromptGame, PromptSettings};
+use crate::rps::{RpsAction, RpsEvent, RpsGame, RpsSettings};
+use crate::rule_set::RuleSet;
+use crate::trivia::{TriviaAction, TriviaEvent, TriviaGame, TriviaSettings};
+use crate::{GameLobbyError, GameLobbyState, PlayerStanding};
+
+/// A game being played in a room. Every call gets the room it's played in,
+/// so the game can read the party, keep score and broadcast its events.
+pub trait Game: fmt::Debug + Send + Sync {
+    /// Set up a fresh match. Scores and round counters have already been
+    /// reset, and it's up to the game to announce the match and get it going.
+    fn start(&mut self, room: &mut GameLobbyState, room_code: &str);
+
+    /// Something a user in the room did in the game
+    fn handle_action(
+        &mut self,
+        room: &mut GameLobbyState,
+        room_code: &str,
+        user_index: usize,
+        action: GameAction,
+    ) -> Result<(), GameLobbyError>;
+
+    /// Called every tick of the round clock while the match is on
+    fn tick(&mut self, room: &mut GameLobbyState, room_code: &str);
+
+    /// A player left the room in the middle of the match
+    fn player_left(&mut self, room: &mut GameLobbyState, room_code: &str);
+
+    /// A player who dropped out in the middle of the match resumed their
+    /// session. Nothing needs doing unless the game counts rounds they missed.
+    fn player_returned(
+        &mut self,
+        _room: &mut GameLobbyState,
+        _room_code: &str,
+        _user_index: usize,
+    ) {
+    }
+
+    /// The match is over. The room announces the winners once it is.
+    fn is_finished(&self) -> bool;
+
+    /// Events that bring someone joining part way up to speed
+    fn snapshot(&self, room: &GameLobbyState) -> Vec<GameEvent>;
+
+    /// Final standings, best first. Defaults to ranking the room's scores.
+    fn standings(&self, room: &GameLobbyState) -> Vec<PlayerStanding> {
+        room.standings()
+    }
+}
+
+/// Which game the host wants to play, and how
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum GameSettings {
+    Rps(RpsSettings),
+    Trivia(TriviaSettings),
+    Prompt(PromptSettings),
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        GameSettings::Rps(RpsSettings::default())
+    }
+}
+
+impl GameSettings {
+    /// Get anything the game needs from the server before it can start, like
+    /// a trivia game's questions, and check the host's settings
+    pub async fn prepare(self, config: &ServerConfig) -> Result<Self, GameLobbyError> {
+        match self {
+            GameSettings::Rps(settings) => Ok(GameSettings::Rps(settings)),
+            GameSettings::Trivia(settings) => Ok(GameSettings::Trivia(
+                settings.load_pack(&config.question_pack_dir).await?,
+            )),
+            GameSettings::Prompt(settings) => Ok(GameSettings::Prompt(settings.prepare(config)?)),
+        }
+    }
+
+    pub fn new_game(&self, rule_set: &RuleSet) -> Box<dyn Game> {
+        match self {
+            GameSettings::Rps(settings) => {
+                Box::new(RpsGame::new(settings.clone(), rule_set.clone()))
+            }
+            GameSettings::Trivia(settings) => Box::new(TriviaGame::new(settings.clone())),
+            GameSettings::Prompt(settings) => Box::new(PromptGame::new(settings.clone())),
+        }
+    }
+}
+
+/// Something a user does in a game, tagged with the game it's for
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum GameAction {
+    Rps(RpsAction),
+    Trivia(TriviaAction),
+    Prompt(PromptAction),
+}
+
+/// Something that happened in a game, tagged with the game it's from
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum GameEvent {
+    Rps(RpsEvent),
+    Trivia(TriviaEvent),
+    Prompt(PromptEvent),
+}
+
+impl From<RpsEvent> for GameEvent {
+    fn from(event: RpsEvent) -> Self {
+        GameEvent::Rps(event)
+    }
+}
+
+impl From<TriviaEvent> for GameEvent {
+    fn from(event: TriviaEvent) -> Self {
+        GameEvent::Trivia(event)
+    }
+}
+
+impl From<PromptEvent> for GameEvent {
+    fn from(event: PromptEvent) -> Self {
+        GameEvent::Prompt(event)
+    }
+}
+
+/// When something `left` from now happens, in milliseconds since the Unix
+/// epoch, so clients can count down to it with their own clocks
+pub fn epoch_millis(left: Duration) -> Option<u64> {
+    (SystemTime::now() + left)
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|since_epoch| since_epoch.as_millis() as u64)
+}
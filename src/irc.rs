@@ -0,0 +1,1171 @@
+//! Lets viewers play from IRC chat, such as Twitch chat, without opening the
+//! game in a browser.
+//!
+//! Chatters become players in whichever room an operator has bound the bridge
+//! to with `!bind CODE`. Their commands are handled just like requests from a
+//! websocket, and the bridge follows the room the same way an overlay does to
+//! post results back to the channel.
+
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use warp::ws::Message;
+
+use log::info;
+
+use crate::game::{GameAction, GameEvent, GameSettings};
+use crate::prompt::{PromptAction, PromptEvent, Submission};
+use crate::rps::{RpsAction, RpsEvent};
+use crate::rule_set::{Hand, RuleSet};
+use crate::trivia::{TriviaAction, TriviaEvent};
+use crate::{
+    handle_request, user_disconnected, Config, ErrorCode, GameLobbyError, GameLobbyRequest,
+    GameLobbyResponse, Games, UserType, Users, MAX_USER_NAME_LEN, NEXT_USER_ID, SHUTTING_DOWN,
+};
+
+pub const DEFAULT_NICK: &str = "usg-bot";
+
+/// Wait between attempts to reach the IRC server
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct IrcConfig {
+    /// host:port of the IRC server
+    pub server: String,
+    pub channel: String,
+    pub nick: String,
+    pub password: Option<String>,
+    /// Nicks allowed to bind chat to a room, lowercased
+    pub operators: Vec<String>,
+}
+
+impl IrcConfig {
+    /// Without any operators given, the owner of the channel gets to run it,
+    /// which is how Twitch names its channels
+    pub fn new(
+        server: String,
+        channel: &str,
+        nick: String,
+        password: Option<String>,
+        operators: Option<Vec<String>>,
+    ) -> Self {
+        let channel = format!("#{}", channel.trim_start_matches('#').to_lowercase());
+        let operators = operators
+            .unwrap_or_else(|| vec![channel.trim_start_matches('#').to_string()])
+            .iter()
+            .map(|nick| nick.trim().to_lowercase())
+            .filter(|nick| !nick.is_empty())
+            .collect();
+
+        IrcConfig {
+            server,
+            channel,
+            nick,
+            password,
+            operators,
+        }
+    }
+}
+
+/// The parts of a line from the IRC server that we care about
+#[derive(Debug)]
+struct IrcLine<'a> {
+    nick: Option<&'a str>,
+    command: &'a str,
+    params: Vec<&'a str>,
+}
+
+impl<'a> IrcLine<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let mut rest = line.trim_end();
+
+        // Skip IRCv3 tags, we don't ask for any but some servers send them anyway
+        if rest.starts_with('@') {
+            rest = rest.split_once(' ')?.1;
+        }
+
+        let mut nick = None;
+        if let Some(prefix) = rest.strip_prefix(':') {
+            let (source, after) = prefix.split_once(' ')?;
+            nick = source.split('!').next();
+            rest = after;
+        }
+
+        let (command, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        if command.is_empty() {
+            return None;
+        }
+
+        let mut params = Vec::new();
+        while !rest.is_empty() {
+            if let Some(trailing) = rest.strip_prefix(':') {
+                params.push(trailing);
+                break;
+            }
+
+            let (param, after) = rest.split_once(' ').unwrap_or((rest, ""));
+            params.push(param);
+            rest = after;
+        }
+
+        Some(IrcLine {
+            nick,
+            command,
+            params,
+        })
+    }
+}
+
+/// What chatters can ask of the bridge
+#[derive(Debug)]
+enum ChatCommand {
+    Bind(String),
+    Unbind,
+    Join,
+    Leave,
+    Hand(Hand),
+    /// An answer to a trivia question, by index
+    Answer(usize),
+    /// A vote in the prompt game, by entry id
+    Vote(usize),
+}
+
+impl ChatCommand {
+    /// Hands are the room's moves with a `!` in front, like `!rock`. While a
+    /// trivia question with `answer_count` answers is being asked, answers
+    /// are their letter instead, like `!b`.
+    fn parse(text: &str, rule_set: &RuleSet, answer_count: usize) -> Option<Self> {
+        let mut words = text.split_whitespace();
+
+        let command = match words.next()?.to_lowercase().as_str() {
+            "!bind" => ChatCommand::Bind(words.next()?.to_uppercase()),
+            "!unbind" => ChatCommand::Unbind,
+            "!join" => ChatCommand::Join,
+            "!leave" => ChatCommand::Leave,
+            // Entries are numbered from 1 in chat
+            "!vote" => ChatCommand::Vote(words.next()?.parse::<usize>().ok()?.checked_sub(1)?),
+            word => {
+                let word = word.strip_prefix('!')?;
+
+                match (0..answer_count).find(|i| word == answer_letter(*i).to_string()) {
+                    Some(answer) => ChatCommand::Answer(answer),
+                    None => ChatCommand::Hand(rule_set.hand(word)?),
+                }
+            }
+        };
+
+        Some(command)
+    }
+}
+
+/// Trivia answers go by letter in chat, starting from 'a'
+fn answer_letter(answer: usize) -> char {
+    (b'a' + answer as u8) as char
+}
+
+/// A chatter playing in the bound room
+#[derive(Debug)]
+struct Chatter {
+    user_id: usize,
+}
+
+struct ChatBridge {
+    irc: IrcConfig,
+    config: Config,
+    games: Games,
+    users: Users,
+    /// The room chat is playing in
+    room_code: Option<String>,
+    /// The moves played in the bound room
+    rule_set: RuleSet,
+    /// Answers to the trivia question being asked in the bound room, if any
+    answer_count: usize,
+    /// The id the bridge follows its room with
+    follower_id: usize,
+    events_tx: mpsc::UnboundedSender<Result<Message, warp::Error>>,
+    /// Chatters' own replies go here to be dropped, anything worth posting
+    /// reaches us through the room
+    chatter_tx: mpsc::UnboundedSender<Result<Message, warp::Error>>,
+    /// By lowercased nick
+    chatters: HashMap<String, Chatter>,
+    /// Lines waiting to be written to the IRC server
+    outbox: Vec<String>,
+}
+
+type Receiver = mpsc::UnboundedReceiver<Result<Message, warp::Error>>;
+
+/// Keep the bridge connected to IRC until the server shuts down
+pub async fn run(irc: IrcConfig, config: Config, games: Games, users: Users) {
+    let (mut bridge, mut events_rx, mut chatter_rx) =
+        ChatBridge::new(irc, config, games, users).await;
+
+    while !SHUTTING_DOWN.load(Ordering::Relaxed) {
+        info!("Connecting to IRC server {}", bridge.irc.server);
+
+        if let Err(e) = bridge.run_connection(&mut events_rx, &mut chatter_rx).await {
+            eprintln!("IRC connection to {} failed: {}", bridge.irc.server, e);
+        }
+
+        // Nobody can throw for chatters while we're gone, so don't leave the
+        // room waiting on them
+        bridge.drop_chatters().await;
+
+        tokio::time::delay_for(RECONNECT_DELAY).await;
+    }
+
+    bridge.users.write().await.remove(&bridge.follower_id);
+}
+
+impl ChatBridge {
+    /// A bridge that isn't bound to a room yet, along with where the room's
+    /// events and the chatters' replies come in
+    async fn new(
+        irc: IrcConfig,
+        config: Config,
+        games: Games,
+        users: Users,
+    ) -> (Self, Receiver, Receiver) {
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let (chatter_tx, chatter_rx) = mpsc::unbounded_channel();
+
+        let follower_id = NEXT_USER_ID.fetch_add(1, Ordering::Relaxed);
+
+        // Only so shutdown can warn chat
+        users.write().await.insert(follower_id, events_tx.clone());
+
+        let bridge = ChatBridge {
+            irc,
+            config,
+            games,
+            users,
+            room_code: None,
+            rule_set: RuleSet::default(),
+            answer_count: 0,
+            follower_id,
+            events_tx,
+            chatter_tx,
+            chatters: HashMap::new(),
+            outbox: Vec::new(),
+        };
+
+        (bridge, events_rx, chatter_rx)
+    }
+
+    async fn run_connection(
+        &mut self,
+        events_rx: &mut Receiver,
+        chatter_rx: &mut Receiver,
+    ) -> io::Result<()> {
+        let stream = TcpStream::connect(self.irc.server.as_str()).await?;
+        let (reader, mut writer) = io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+
+        if let Some(password) = self.irc.password.as_ref() {
+            self.outbox.push(format!("PASS {}", password));
+        }
+        self.outbox.push(format!("NICK {}", self.irc.nick));
+        self.outbox
+            .push(format!("USER {} 0 * :Untitled Stream Game", self.irc.nick));
+        self.outbox.push(format!("JOIN {}", self.irc.channel));
+
+        loop {
+            for line in self.outbox.drain(..) {
+                writer.write_all(line.as_bytes()).await?;
+                writer.write_all(b"\r\n").await?;
+            }
+
+            tokio::select! {
+                line = lines.next() => match line {
+                    Some(line) => self.handle_line(&line?).await,
+                    None => return Ok(()),
+                },
+                Some(event) = events_rx.recv() => {
+                    // Shutdown closes everyone's socket, ours is to IRC. Rooms
+                    // close the sockets following them too, but that only
+                    // means the room's gone, which RoomClosed already said.
+                    if SHUTTING_DOWN.load(Ordering::Relaxed)
+                        && event.as_ref().is_ok_and(|msg| msg.is_close())
+                    {
+                        writer.write_all(b"QUIT :Server shutting down\r\n").await?;
+                        self.users.write().await.remove(&self.follower_id);
+                        return Ok(());
+                    }
+
+                    self.handle_event(event).await
+                }
+                Some(_) = chatter_rx.recv() => {},
+            }
+        }
+    }
+
+    async fn handle_line(&mut self, line: &str) {
+        let line = match IrcLine::parse(line) {
+            Some(line) => line,
+            None => return,
+        };
+
+        match (line.command, line.params.as_slice()) {
+            ("PING", params) => {
+                self.outbox
+                    .push(format!("PONG :{}", params.first().unwrap_or(&"")));
+            }
+            ("PRIVMSG", [target, text]) if target.eq_ignore_ascii_case(&self.irc.channel) => {
+                let nick = match line.nick {
+                    Some(nick) => nick,
+                    None => return,
+                };
+
+                if let Some(command) = ChatCommand::parse(text, &self.rule_set, self.answer_count) {
+                    self.handle_command(nick, command).await;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn handle_command(&mut self, nick: &str, command: ChatCommand) {
+        match command {
+            ChatCommand::Bind(room_code) if self.is_operator(nick) => self.bind(room_code).await,
+            ChatCommand::Unbind if self.is_operator(nick) => {
+                self.unbind().await;
+                self.say("Chat is no longer playing in a room".to_string());
+            }
+            ChatCommand::Bind(_) | ChatCommand::Unbind => {}
+            ChatCommand::Join => {
+                self.join(nick).await;
+            }
+            ChatCommand::Leave => self.leave(nick).await,
+            ChatCommand::Hand(hand) => {
+                self.play(nick, GameAction::Rps(RpsAction::Throw { hand }))
+                    .await
+            }
+            ChatCommand::Answer(answer) => {
+                self.play(nick, GameAction::Trivia(TriviaAction::Answer { answer }))
+                    .await
+            }
+            ChatCommand::Vote(entry) => {
+                self.play(nick, GameAction::Prompt(PromptAction::Vote { entry }))
+                    .await
+            }
+        }
+    }
+
+    /// Post anything chat should hear about from the room
+    async fn handle_event(&mut self, event: Result<Message, warp::Error>) {
+        let resp = match event
+            .ok()
+            .as_ref()
+            .and_then(|msg| msg.to_str().ok())
+            .and_then(|s| serde_json::from_str(s).ok())
+        {
+            Some(resp) => resp,
+            None => return,
+        };
+
+        match resp {
+            GameLobbyResponse::GameStart {
+                settings: GameSettings::Trivia(_),
+                ..
+            } => self.say("Trivia time! Answer with !a, !b and so on".to_string()),
+            GameLobbyResponse::GameStart {
+                settings: GameSettings::Prompt(_),
+                ..
+            } => self.say(
+                "Prompt game! Answer in the browser, then !vote for your favourite here"
+                    .to_string(),
+            ),
+            GameLobbyResponse::GameStart {
+                total_rounds: 0, ..
+            } => self.say(format!(
+                "The bracket is set! Type {} to play",
+                self.hand_commands()
+            )),
+            GameLobbyResponse::GameStart { total_rounds, .. } => self.say(format!(
+                "Game on! {} rounds. Type {} to play",
+                total_rounds,
+                self.hand_commands()
+            )),
+            GameLobbyResponse::GameEvent {
+                event: GameEvent::Rps(event),
+                ..
+            } => self.handle_rps_event(event),
+            GameLobbyResponse::GameEvent {
+                event: GameEvent::Trivia(event),
+                ..
+            } => self.handle_trivia_event(event),
+            GameLobbyResponse::GameEvent {
+                event: GameEvent::Prompt(event),
+                ..
+            } => self.handle_prompt_event(event),
+            GameLobbyResponse::GameOver { winners, .. } => {
+                self.answer_count = 0;
+                self.say(format!("Game over! Winner(s): {}", winners.join(", ")))
+            }
+            GameLobbyResponse::RoomClosed { room_code, .. }
+                if self.room_code.as_ref() == Some(&room_code) =>
+            {
+                self.say(format!("Room {} has closed", room_code));
+
+                // The room's gone, and everyone in it with it
+                self.room_code = None;
+                self.chatters.clear();
+            }
+            GameLobbyResponse::ServerShuttingDown { seconds } => self.say(format!(
+                "The server is shutting down in {} seconds",
+                seconds
+            )),
+            _ => {}
+        }
+    }
+
+    /// The room's moves as chat commands, like "!rock, !paper or !scissors"
+    fn hand_commands(&self) -> String {
+        let commands: Vec<String> = self
+            .rule_set
+            .moves
+            .iter()
+            .map(|m| format!("!{}", m.to_lowercase()))
+            .collect();
+
+        match commands.split_last() {
+            Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+            _ => commands.join(""),
+        }
+    }
+
+    fn handle_rps_event(&mut self, event: RpsEvent) {
+        match event {
+            RpsEvent::RoundStarted {
+                round,
+                total_rounds: 0,
+                seconds_left: Some(seconds_left),
+                ..
+            } => self.say(format!(
+                "Round {}: {} seconds to throw",
+                round, seconds_left
+            )),
+            RpsEvent::RoundStarted {
+                round,
+                total_rounds,
+                seconds_left: Some(seconds_left),
+                ..
+            } => self.say(format!(
+                "Round {} of {}: {} seconds to throw",
+                round, total_rounds, seconds_left
+            )),
+            // Post the draw for each new bracket round, but not every result in it
+            RpsEvent::BracketUpdate { bracket, .. }
+                if bracket
+                    .matches
+                    .iter()
+                    .all(|m| m.winner.is_none() && m.draws == 0) =>
+            {
+                let matches: Vec<String> = bracket
+                    .matches
+                    .iter()
+                    .map(|m| format!("{} vs {}", m.players[0], m.players[1]))
+                    .collect();
+
+                let mut text = format!("Bracket round {}: {}", bracket.round, matches.join(", "));
+                if !bracket.byes.is_empty() {
+                    text.push_str(&format!(". Bye: {}", bracket.byes.join(", ")));
+                }
+                self.say(text);
+            }
+            RpsEvent::RoundResult {
+                user_name,
+                player_hand,
+                server_hand,
+                outcome,
+                opponent: None,
+            } => self.say(format!(
+                "{} threw {} against {}: {:?}",
+                user_name, player_hand, server_hand, outcome
+            )),
+            // Both players in a PvP pair get a result, only post one of them
+            RpsEvent::RoundResult {
+                user_name,
+                player_hand,
+                server_hand,
+                outcome,
+                opponent: Some(opponent),
+            } if user_name < opponent => self.say(format!(
+                "{} threw {} against {}'s {}: {:?}",
+                user_name, player_hand, opponent, server_hand, outcome
+            )),
+            _ => {}
+        }
+    }
+
+    fn handle_trivia_event(&mut self, event: TriviaEvent) {
+        match event {
+            TriviaEvent::QuestionStarted {
+                round,
+                total_rounds,
+                question,
+                answers,
+                seconds_left,
+                ..
+            } => {
+                self.answer_count = answers.len();
+
+                let answers: Vec<String> = answers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, answer)| format!("!{} {}", answer_letter(i), answer))
+                    .collect();
+
+                self.say(format!(
+                    "Question {} of {} ({}s): {}",
+                    round, total_rounds, seconds_left, question
+                ));
+                self.say(answers.join(" | "));
+            }
+            TriviaEvent::QuestionResult {
+                correct,
+                answers,
+                results,
+                ..
+            } => {
+                self.answer_count = 0;
+
+                let right: Vec<String> = results
+                    .into_iter()
+                    .filter(|r| r.correct)
+                    .map(|r| r.user_name)
+                    .collect();
+
+                self.say(format!(
+                    "The answer was {}) {}. Got it right: {}",
+                    answer_letter(correct),
+                    answers.get(correct).map_or("", |a| a.answer.as_str()),
+                    if right.is_empty() {
+                        "nobody".to_string()
+                    } else {
+                        right.join(", ")
+                    }
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_prompt_event(&mut self, event: PromptEvent) {
+        match event {
+            // Answers posted here would give away whose entry is whose, so
+            // they're only taken in the browser
+            PromptEvent::PromptStarted {
+                round,
+                total_rounds,
+                prompt,
+                seconds_left,
+                ..
+            } => self.say(format!(
+                "Round {} of {} ({}s to answer in the browser): {}",
+                round, total_rounds, seconds_left, prompt
+            )),
+            PromptEvent::VotingOpen { entries, .. } => {
+                self.say("Vote for your favourite with !vote and its number:".to_string());
+
+                for entry in entries {
+                    let shown = match entry.submission {
+                        Submission::Text(text) => text,
+                        Submission::Drawing(_) => "(a drawing, see the stream)".to_string(),
+                    };
+                    self.say(format!("{}) {}", entry.id + 1, shown));
+                }
+            }
+            PromptEvent::EntryRemoved { entry, .. } => {
+                self.say(format!("Entry {} was taken down", entry + 1))
+            }
+            PromptEvent::RoundResults { results, .. } => {
+                let most_votes = results.first().map_or(0, |r| r.votes);
+                let winners: Vec<String> = results
+                    .iter()
+                    .filter(|r| most_votes > 0 && r.votes == most_votes)
+                    .map(|r| format!("{} ({})", r.user_name, r.id + 1))
+                    .collect();
+
+                if winners.is_empty() {
+                    self.say("No votes this round".to_string());
+                } else {
+                    self.say(format!(
+                        "Most votes with {}: {}",
+                        most_votes,
+                        winners.join(", ")
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn is_operator(&self, nick: &str) -> bool {
+        self.irc.operators.contains(&nick.to_lowercase())
+    }
+
+    /// Follow a room and let chat play in it
+    async fn bind(&mut self, room_code: String) {
+        self.unbind().await;
+
+        {
+            let mut games = self.games.write().await;
+
+            let game_state = match games.get_mut(&room_code) {
+                Some(game_state) => game_state,
+                None => {
+                    drop(games);
+                    self.say(format!("There's no room {}", room_code));
+                    return;
+                }
+            };
+
+            game_state
+                .overlays
+                .insert(self.follower_id, self.events_tx.clone());
+            self.rule_set = game_state.rule_set.clone();
+            self.answer_count = 0;
+        }
+
+        info!(
+            "({}) IRC chat {} bound to room",
+            room_code, self.irc.channel
+        );
+
+        self.say(format!(
+            "Chat is playing in room {}! Type !join to get in the game",
+            room_code
+        ));
+        self.room_code = Some(room_code);
+    }
+
+    /// Stop following the room, taking chat's players out of it
+    async fn unbind(&mut self) {
+        self.drop_chatters().await;
+
+        if let Some(room_code) = self.room_code.take() {
+            if let Some(game_state) = self.games.write().await.get_mut(&room_code) {
+                game_state.overlays.remove(&self.follower_id);
+            }
+        }
+    }
+
+    async fn drop_chatters(&mut self) {
+        for (_, chatter) in self.chatters.drain() {
+            user_disconnected(chatter.user_id, &self.users, &self.games).await;
+        }
+    }
+
+    /// Put a chatter in the room, returning whether they're in it
+    async fn join(&mut self, nick: &str) -> bool {
+        let key = nick.to_lowercase();
+        if self.chatters.contains_key(&key) {
+            return true;
+        }
+
+        let room_code = match self.room_code.clone() {
+            Some(room_code) => room_code,
+            None => {
+                self.say(format!("@{} there's no game to join yet", nick));
+                return false;
+            }
+        };
+
+        let user_id = NEXT_USER_ID.fetch_add(1, Ordering::Relaxed);
+        let mut user_name: String = nick.chars().take(MAX_USER_NAME_LEN).collect();
+
+        let mut result = self.login(user_id, &user_name, &room_code).await;
+
+        // Nicks can clash with someone on the web, or have characters the room
+        // doesn't allow, so take the room's suggestion rather than making them pick
+        if let Err(GameLobbyError {
+            code: ErrorCode::NameTaken,
+            suggested_name: Some(suggested_name),
+            ..
+        })
+        | Err(GameLobbyError {
+            code: ErrorCode::InvalidName,
+            suggested_name: Some(suggested_name),
+            ..
+        }) = &result
+        {
+            user_name = suggested_name.clone();
+            result = self.login(user_id, &user_name, &room_code).await;
+        }
+
+        match result {
+            Ok(()) => {
+                info!("({}) {} joined from IRC as {}", room_code, nick, user_name);
+
+                self.say(format!("@{} you're in as {}", nick, user_name));
+                self.chatters.insert(key, Chatter { user_id });
+                true
+            }
+            Err(e) => {
+                self.say(format!("@{} {}", nick, e.message));
+                false
+            }
+        }
+    }
+
+    async fn login(
+        &self,
+        user_id: usize,
+        user_name: &str,
+        room_code: &str,
+    ) -> Result<(), GameLobbyError> {
+        let req = GameLobbyRequest::UserLogin {
+            user_name: user_name.to_string(),
+            user_type: UserType::Player,
+            room_code: room_code.to_string(),
+        };
+
+        handle_request(user_id, &self.chatter_tx, &self.games, &self.config, req).await
+    }
+
+    async fn leave(&mut self, nick: &str) {
+        if let Some(chatter) = self.chatters.remove(&nick.to_lowercase()) {
+            user_disconnected(chatter.user_id, &self.users, &self.games).await;
+        }
+    }
+
+    /// Playing joins the game first, so chatters can jump right in
+    async fn play(&mut self, nick: &str, action: GameAction) {
+        if !self.join(nick).await {
+            return;
+        }
+
+        let (room_code, chatter) = match (
+            self.room_code.clone(),
+            self.chatters.get(&nick.to_lowercase()),
+        ) {
+            (Some(room_code), Some(chatter)) => (room_code, chatter),
+            _ => return,
+        };
+
+        let req = GameLobbyRequest::GameAction { room_code, action };
+
+        if let Err(e) = handle_request(
+            chatter.user_id,
+            &self.chatter_tx,
+            &self.games,
+            &self.config,
+            req,
+        )
+        .await
+        {
+            self.say(format!("@{} {}", nick, e.message));
+        }
+    }
+
+    /// Post to the channel. Whatever's said stays on one line, so text from
+    /// the room can't slip extra commands to the IRC server.
+    fn say(&mut self, text: String) {
+        let text: String = text
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+
+        self.outbox
+            .push(format!("PRIVMSG {} :{}", self.irc.channel, text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use serde_json::{json, Value};
+    use tokio::io::{Lines, ReadHalf, WriteHalf};
+    use tokio::net::TcpListener;
+    use tokio::sync::RwLock;
+    use tokio::time::timeout;
+
+    use crate::config::{ServerConfig, ServerOptions};
+
+    /// Longest the tests wait on the bridge or the server before giving up
+    const WAIT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn parses_a_line_with_a_prefix_and_trailing_param() {
+        let line =
+            IrcLine::parse(":alice!alice@alice.tmi.twitch.tv PRIVMSG #streamer :!join now\r\n")
+                .unwrap();
+
+        assert_eq!(line.nick, Some("alice"));
+        assert_eq!(line.command, "PRIVMSG");
+        assert_eq!(line.params, vec!["#streamer", "!join now"]);
+    }
+
+    #[test]
+    fn parses_a_line_without_a_prefix() {
+        let line = IrcLine::parse("PING :tmi.twitch.tv").unwrap();
+
+        assert_eq!(line.nick, None);
+        assert_eq!(line.command, "PING");
+        assert_eq!(line.params, vec!["tmi.twitch.tv"]);
+    }
+
+    #[test]
+    fn skips_tags() {
+        let line = IrcLine::parse(
+            "@badge-info=;color=#FF0000;display-name=Alice :alice!alice@alice.tmi.twitch.tv PRIVMSG #streamer :!rock",
+        )
+        .unwrap();
+
+        assert_eq!(line.nick, Some("alice"));
+        assert_eq!(line.command, "PRIVMSG");
+        assert_eq!(line.params, vec!["#streamer", "!rock"]);
+    }
+
+    #[test]
+    fn keeps_middle_params_apart() {
+        let line = IrcLine::parse(":tmi.twitch.tv 353 usg-bot = #streamer :alice bob").unwrap();
+
+        assert_eq!(line.nick, Some("tmi.twitch.tv"));
+        assert_eq!(line.command, "353");
+        assert_eq!(line.params, vec!["usg-bot", "=", "#streamer", "alice bob"]);
+    }
+
+    #[test]
+    fn rejects_lines_without_a_command() {
+        assert!(IrcLine::parse("").is_none());
+        assert!(IrcLine::parse(":alice!alice@alice").is_none());
+        assert!(IrcLine::parse("@color=#FF0000").is_none());
+    }
+
+    #[test]
+    fn parses_room_commands() {
+        let rule_set = RuleSet::default();
+
+        assert!(matches!(
+            ChatCommand::parse("!bind abcd", &rule_set, 0),
+            Some(ChatCommand::Bind(code)) if code == "ABCD"
+        ));
+        assert!(matches!(
+            ChatCommand::parse("!UNBIND", &rule_set, 0),
+            Some(ChatCommand::Unbind)
+        ));
+        assert!(matches!(
+            ChatCommand::parse("!join", &rule_set, 0),
+            Some(ChatCommand::Join)
+        ));
+        assert!(matches!(
+            ChatCommand::parse("!leave please", &rule_set, 0),
+            Some(ChatCommand::Leave)
+        ));
+        assert!(ChatCommand::parse("!bind", &rule_set, 0).is_none());
+    }
+
+    #[test]
+    fn parses_hands_from_the_rule_set() {
+        let rule_set = RuleSet::default();
+
+        assert!(matches!(
+            ChatCommand::parse("!Rock", &rule_set, 0),
+            Some(ChatCommand::Hand(hand)) if Some(hand.clone()) == rule_set.hand("rock")
+        ));
+        assert!(ChatCommand::parse("!lizard", &rule_set, 0).is_none());
+    }
+
+    #[test]
+    fn parses_answers_only_while_a_question_is_asked() {
+        let rule_set = RuleSet::default();
+
+        assert!(matches!(
+            ChatCommand::parse("!b", &rule_set, 4),
+            Some(ChatCommand::Answer(1))
+        ));
+        assert!(ChatCommand::parse("!e", &rule_set, 4).is_none());
+        assert!(ChatCommand::parse("!b", &rule_set, 0).is_none());
+    }
+
+    #[test]
+    fn parses_votes_but_not_submissions() {
+        let rule_set = RuleSet::default();
+
+        // Everyone could see who sent what, so entries only come from the browser
+        assert!(ChatCommand::parse("!submit a cat in a hat", &rule_set, 0).is_none());
+
+        // Entries are numbered from 1 in chat
+        assert!(matches!(
+            ChatCommand::parse("!vote 2", &rule_set, 0),
+            Some(ChatCommand::Vote(1))
+        ));
+        assert!(ChatCommand::parse("!vote 0", &rule_set, 0).is_none());
+        assert!(ChatCommand::parse("!vote two", &rule_set, 0).is_none());
+    }
+
+    #[test]
+    fn ignores_chatter_that_isnt_a_command() {
+        let rule_set = RuleSet::default();
+
+        assert!(ChatCommand::parse("", &rule_set, 0).is_none());
+        assert!(ChatCommand::parse("rock", &rule_set, 0).is_none());
+        assert!(ChatCommand::parse("!dance", &rule_set, 0).is_none());
+        assert!(ChatCommand::parse("!", &rule_set, 0).is_none());
+    }
+
+    #[tokio::test]
+    async fn says_everything_on_one_line() {
+        let config: Config =
+            Arc::new(ServerConfig::from_options(ServerOptions::default()).unwrap());
+        let irc = IrcConfig::new(
+            String::new(),
+            "streamer",
+            DEFAULT_NICK.to_string(),
+            None,
+            None,
+        );
+        let users: Users = Arc::new(RwLock::new(HashMap::new()));
+
+        let (mut bridge, _, _) =
+            ChatBridge::new(irc, config, Arc::new(RwLock::new(HashMap::new())), users).await;
+        bridge.say("Draw a cat\r\nPRIVMSG #streamer :pwned\n".to_string());
+
+        assert_eq!(
+            bridge.outbox,
+            vec!["PRIVMSG #streamer :Draw a cat  PRIVMSG #streamer :pwned "]
+        );
+    }
+
+    /// The IRC server's end of the bridge's connection
+    struct MockServer {
+        lines: Lines<BufReader<ReadHalf<TcpStream>>>,
+        writer: WriteHalf<TcpStream>,
+    }
+
+    impl MockServer {
+        async fn send(&mut self, line: &str) {
+            self.writer.write_all(line.as_bytes()).await.unwrap();
+            self.writer.write_all(b"\r\n").await.unwrap();
+        }
+
+        async fn next_line(&mut self) -> String {
+            timeout(WAIT, self.lines.next())
+                .await
+                .expect("The bridge went quiet")
+                .expect("The bridge hung up")
+                .unwrap()
+        }
+
+        /// Skip ahead to the first line starting with `start`
+        async fn wait_for(&mut self, start: &str) -> String {
+            loop {
+                let line = self.next_line().await;
+                if line.starts_with(start) {
+                    return line;
+                }
+            }
+        }
+    }
+
+    /// A bridge connected to a mock IRC server, with a room hosted for it
+    struct Harness {
+        server: MockServer,
+        bridge: tokio::task::JoinHandle<io::Result<()>>,
+        games: Games,
+        config: Config,
+        host_id: usize,
+        host_tx: mpsc::UnboundedSender<Result<Message, warp::Error>>,
+        host_rx: Receiver,
+        room_code: String,
+    }
+
+    impl Harness {
+        /// Connect the bridge and see it through logging in to IRC
+        async fn start() -> Self {
+            let config: Config =
+                Arc::new(ServerConfig::from_options(ServerOptions::default()).unwrap());
+            let games: Games = Arc::new(RwLock::new(HashMap::new()));
+            let users: Users = Arc::new(RwLock::new(HashMap::new()));
+
+            let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let irc = IrcConfig::new(
+                listener.local_addr().unwrap().to_string(),
+                "Streamer",
+                DEFAULT_NICK.to_string(),
+                None,
+                None,
+            );
+
+            let (mut bridge, mut events_rx, mut chatter_rx) =
+                ChatBridge::new(irc, config.clone(), games.clone(), users).await;
+            let bridge = tokio::spawn(async move {
+                bridge.run_connection(&mut events_rx, &mut chatter_rx).await
+            });
+
+            let (stream, _) = timeout(WAIT, listener.accept()).await.unwrap().unwrap();
+            let (reader, writer) = io::split(stream);
+            let mut server = MockServer {
+                lines: BufReader::new(reader).lines(),
+                writer,
+            };
+
+            assert_eq!(server.next_line().await, "NICK usg-bot");
+            assert_eq!(
+                server.next_line().await,
+                "USER usg-bot 0 * :Untitled Stream Game"
+            );
+            assert_eq!(server.next_line().await, "JOIN #streamer");
+
+            let host_id = NEXT_USER_ID.fetch_add(1, Ordering::Relaxed);
+            let (host_tx, host_rx) = mpsc::unbounded_channel();
+
+            let mut harness = Harness {
+                server,
+                bridge,
+                games,
+                config,
+                host_id,
+                host_tx,
+                host_rx,
+                room_code: String::new(),
+            };
+
+            harness
+                .host_request(
+                    json!({ "HostNewGame": { "user_name": "Host", "user_type": "Host" } }),
+                )
+                .await;
+            let session = wait_for_response(&mut harness.host_rx, "SessionStarted").await;
+            harness.room_code = session["room_code"].as_str().unwrap().to_string();
+
+            harness
+        }
+
+        async fn host_request(&mut self, req: Value) {
+            let req: GameLobbyRequest = serde_json::from_value(req).unwrap();
+            handle_request(self.host_id, &self.host_tx, &self.games, &self.config, req)
+                .await
+                .unwrap();
+        }
+
+        /// Have the channel's owner bind chat to the room
+        async fn bind(&mut self) {
+            self.server
+                .send(&format!(
+                    ":streamer!streamer@streamer.tmi.twitch.tv PRIVMSG #streamer :!bind {}",
+                    self.room_code.to_lowercase()
+                ))
+                .await;
+            assert_eq!(
+                self.server.next_line().await,
+                format!(
+                    "PRIVMSG #streamer :Chat is playing in room {}! Type !join to get in the game",
+                    self.room_code
+                )
+            );
+        }
+
+        /// Hang up on the bridge, which should end its connection cleanly
+        async fn hang_up(self) {
+            drop(self.server);
+            timeout(WAIT, self.bridge).await.unwrap().unwrap().unwrap();
+        }
+    }
+
+    /// Skip ahead to the first response of the `kind` given
+    async fn wait_for_response(rx: &mut Receiver, kind: &str) -> Value {
+        loop {
+            let msg = timeout(WAIT, rx.recv())
+                .await
+                .expect("The server went quiet")
+                .unwrap()
+                .unwrap();
+
+            let resp: Value = serde_json::from_str(msg.to_str().unwrap()).unwrap();
+            if let Some(body) = resp.get(kind) {
+                return body.clone();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_pings() {
+        let mut harness = Harness::start().await;
+
+        harness.server.send("PING :tmi.twitch.tv").await;
+        assert_eq!(harness.server.next_line().await, "PONG :tmi.twitch.tv");
+
+        harness.hang_up().await;
+    }
+
+    #[tokio::test]
+    async fn plays_a_round_from_chat() {
+        let mut harness = Harness::start().await;
+
+        // Only the channel's owner gets to bind it
+        harness
+            .server
+            .send(&format!(
+                ":alice!alice@alice.tmi.twitch.tv PRIVMSG #streamer :!bind {}",
+                harness.room_code
+            ))
+            .await;
+        harness.bind().await;
+
+        harness
+            .server
+            .send(":alice!alice@alice.tmi.twitch.tv PRIVMSG #streamer :!join")
+            .await;
+        assert_eq!(
+            harness.server.next_line().await,
+            "PRIVMSG #streamer :@alice you're in as alice"
+        );
+
+        harness
+            .host_request(
+                json!({ "HostStartGame": { "room_code": harness.room_code, "rounds": 1 } }),
+            )
+            .await;
+        harness
+            .server
+            .wait_for("PRIVMSG #streamer :Round 1 of 1: 30 seconds to throw")
+            .await;
+
+        harness
+            .server
+            .send("@badge-info=;color=#FF0000 :alice!alice@alice.tmi.twitch.tv PRIVMSG #streamer :!rock")
+            .await;
+        let result = harness
+            .server
+            .wait_for("PRIVMSG #streamer :alice threw Rock against ")
+            .await;
+        let outcome = result.rsplit(": ").next().unwrap();
+        assert!(
+            ["Win", "Lose", "Draw"].contains(&outcome),
+            "{} has no outcome",
+            result
+        );
+
+        harness.hang_up().await;
+    }
+
+    #[tokio::test]
+    async fn stays_connected_when_its_room_closes() {
+        let mut harness = Harness::start().await;
+        harness.bind().await;
+
+        harness
+            .host_request(json!({ "HostEndGame": { "room_code": harness.room_code } }))
+            .await;
+        assert_eq!(
+            harness.server.next_line().await,
+            format!("PRIVMSG #streamer :Room {} has closed", harness.room_code)
+        );
+
+        // Closing the room isn't shutting down, so there's no QUIT
+        harness.server.send("PING :tmi.twitch.tv").await;
+        assert_eq!(harness.server.next_line().await, "PONG :tmi.twitch.tv");
+
+        harness.hang_up().await;
+    }
+}
@@ -1,12 +1,27 @@
 // #![deny(warnings)]
+mod bracket;
+mod config;
+mod game;
+mod irc;
+mod moderation;
+mod prompt;
+mod question_pack;
+mod room_code;
+mod rps;
+mod rule_set;
+mod strategy;
+mod trivia;
+
 use std::collections::HashMap;
 use std::iter;
 use std::sync::{
-    atomic::{AtomicUsize, Ordering},
+    atomic::{AtomicBool, AtomicUsize, Ordering},
     Arc,
 };
+use std::time::{Duration, Instant};
 
 use futures::{FutureExt, StreamExt};
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::{mpsc, RwLock};
 use warp::ws::{Message, WebSocket};
 use warp::Filter;
@@ -16,14 +31,20 @@ use serde_json::json;
 
 use log::info;
 
-use rand::{
-    distributions::{Alphanumeric, Distribution, Standard},
-    thread_rng, Rng,
-};
+use config::ServerConfig;
+use game::{Game, GameAction, GameEvent, GameSettings};
+use question_pack::{QuestionPack, QuestionPackSummary};
+use rule_set::{RoundOutcome, RuleSet, Variant};
+use strategy::ThrowHistory;
+
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
 
 /// Our global unique user id counter.
 static NEXT_USER_ID: AtomicUsize = AtomicUsize::new(1);
 
+/// Set once the server has been told to stop, so no new rooms get opened.
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
 /// Our state of currently connected users.
 ///
 /// - Key is their id
@@ -32,20 +53,213 @@ type Users = Arc<RwLock<HashMap<usize, mpsc::UnboundedSender<Result<Message, war
 
 type Games = Arc<RwLock<HashMap<String, GameLobbyState>>>;
 
+type Config = Arc<ServerConfig>;
+
+/// Number of rounds in a match when the host doesn't ask for a specific amount
+const DEFAULT_ROUNDS: usize = 5;
+/// Upper bound on the number of rounds a host can ask for
+const MAX_ROUNDS: usize = 25;
+/// Longest display name a user can pick, in characters
+const MAX_USER_NAME_LEN: usize = 16;
+
+/// How often the sweeper looks for rooms to close
+const ROOM_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+/// How often rooms are checked for rounds that have run out of time
+const ROUND_CLOCK_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest shutdown waits for sockets to finish closing once they've been told to
+const SHUTDOWN_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a room waits for its host to resume their session before closing
+const HOST_RECONNECT_GRACE: Duration = Duration::from_secs(30);
+
 #[derive(Deserialize, Serialize, Debug)]
 enum GameLobbyResponse {
+    SessionStarted {
+        room_code: String,
+        user_name: String,
+        user_type: UserType,
+        session_token: String,
+        /// The moves the room plays with
+        rule_set: RuleSet,
+    },
     PartyUpdate {
         room_code: String,
         users: Vec<String>,
+        max_players: usize,
+        audience_count: usize,
     },
     GameStart {
         room_code: String,
+        /// 0 for games that go until someone wins, like brackets
+        total_rounds: usize,
+        settings: GameSettings,
+    },
+    ScoreUpdate {
+        room_code: String,
+        round: usize,
+        total_rounds: usize,
+        scores: Vec<PlayerScoreEntry>,
+    },
+    GameOver {
+        room_code: String,
+        winners: Vec<String>,
+        standings: Vec<PlayerStanding>,
+    },
+    ServerShuttingDown {
+        seconds: u64,
+    },
+    /// The question packs trivia can be played with
+    QuestionPacks {
+        packs: Vec<QuestionPackSummary>,
+    },
+    /// Something happened in the game being played
+    GameEvent {
+        room_code: String,
+        event: GameEvent,
+    },
+    RoomExpiring {
+        room_code: String,
+        seconds_left: u64,
+    },
+    /// The host lost their connection. The room closes unless they resume
+    /// their session in time.
+    HostAway {
+        room_code: String,
+        seconds_left: u64,
+    },
+    /// The host resumed their session, so the room stays open
+    HostBack {
+        room_code: String,
+    },
+    RoomClosed {
+        room_code: String,
+        reason: RoomClosedReason,
     },
-    ServerHand {
-        hand: RPSHand,
+    Error {
+        code: ErrorCode,
+        message: String,
+        request: Option<String>,
+        suggested_name: Option<String>,
     },
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+enum RoomClosedReason {
+    HostEndedGame,
+    HostDisconnected,
+    /// Nobody did anything in the room for too long
+    Idle,
+    /// Everyone in the room disconnected
+    Empty,
+}
+
+/// Machine readable reasons a request was turned down
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+enum ErrorCode {
+    /// The message wasn't a valid `GameLobbyRequest`
+    BadRequest,
+    UnknownRoom,
+    GameAlreadyStarted,
+    GameNotStarted,
+    /// The name is empty, too long or uses characters we don't allow
+    InvalidName,
+    NameTaken,
+    NotHost,
+    NotInRoom,
+    /// Only players can do that, not the audience
+    NotAPlayer,
+    /// Only the audience can do that
+    NotAudience,
+    /// Voting for the round is over
+    VotingClosed,
+    /// The round ran out of time before the hand was thrown
+    RoundTimedOut,
+    /// The player doesn't have a bracket match to play right now
+    NoMatch,
+    /// The host's rule-set doesn't make a fair game
+    InvalidRuleSet,
+    /// The hand isn't one of the room's moves
+    InvalidHand,
+    /// The action is for a different game than the one being played
+    WrongGame,
+    UnknownQuestionPack,
+    /// The question pack is there, but can't be played
+    InvalidQuestionPack,
+    /// The answer isn't one of the question's answers
+    InvalidAnswer,
+    /// The host's game settings don't work
+    InvalidSettings,
+    /// The submission is the wrong kind, or doesn't fit
+    InvalidSubmission,
+    /// A moderator turned the submission away
+    SubmissionRejected,
+    /// The vote isn't for an entry that can be voted for
+    InvalidVote,
+    /// The connection already belongs to a user in a room
+    AlreadyInRoom,
+    AlreadyPlayedRound,
+    InvalidSession,
+    /// The server can't open any more rooms
+    NoRoomsAvailable,
+    /// The room has as many players as it can hold
+    RoomFull,
+    /// The server is on its way down and isn't opening new rooms
+    ShuttingDown,
+}
+
+#[derive(Debug, Clone)]
+struct GameLobbyError {
+    code: ErrorCode,
+    message: String,
+    /// A name the user could pick instead, when theirs was turned down
+    suggested_name: Option<String>,
+}
+
+impl GameLobbyError {
+    fn new(code: ErrorCode, message: String) -> Self {
+        GameLobbyError {
+            code,
+            message,
+            suggested_name: None,
+        }
+    }
+
+    fn with_suggested_name(mut self, suggested_name: Option<String>) -> Self {
+        self.suggested_name = suggested_name;
+        self
+    }
+
+    fn unknown_room(room_code: &str) -> Self {
+        GameLobbyError::new(
+            ErrorCode::UnknownRoom,
+            format!("Room code: {} does not exist", room_code),
+        )
+    }
+
+    fn already_in_room(room_code: &str) -> Self {
+        GameLobbyError::new(
+            ErrorCode::AlreadyInRoom,
+            format!("Already joined room {}", room_code),
+        )
+    }
+
+    fn wrong_game() -> Self {
+        GameLobbyError::new(
+            ErrorCode::WrongGame,
+            "That isn't part of the game being played".to_string(),
+        )
+    }
+
+    /// `request` is the kind of request that failed, if we got far enough to know
+    fn into_response(self, request: Option<&str>) -> GameLobbyResponse {
+        GameLobbyResponse::Error {
+            code: self.code,
+            message: self.message,
+            request: request.map(|r| r.to_string()),
+            suggested_name: self.suggested_name,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 enum GameLobbyRequest {
     UserLogin {
@@ -56,30 +270,52 @@ enum GameLobbyRequest {
     HostNewGame {
         user_name: String,
         user_type: UserType,
+        /// Most players the room takes, capped by the server's own limit
+        #[serde(default)]
+        max_players: Option<usize>,
+        /// Let players join while a game is being played
+        #[serde(default)]
+        allow_late_join: bool,
+        /// The moves the room plays with
+        #[serde(default)]
+        rule_set: Variant,
     },
     HostStartGame {
         room_code: String,
+        #[serde(default)]
+        rounds: Option<usize>,
+        #[serde(default)]
+        game: GameSettings,
     },
-    PlayerHand {
-        user_name: String,
+    HostRestartGame {
         room_code: String,
-        hand: RPSHand,
+    },
+    HostEndGame {
+        room_code: String,
+    },
+    ListQuestionPacks,
+    Resume {
+        token: String,
+    },
+    /// Anything done in the game itself, which is up to the game to handle
+    GameAction {
+        room_code: String,
+        action: GameAction,
     },
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
-enum RPSHand {
-    Rock,
-    Paper,
-    Scissors,
-}
-
-impl Distribution<RPSHand> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> RPSHand {
-        match rng.gen_range(0, 3) {
-            0 => RPSHand::Rock,
-            1 => RPSHand::Paper,
-            _ => RPSHand::Scissors,
+impl GameLobbyRequest {
+    /// Name of the request, used to tell the client which request an error belongs to
+    fn kind(&self) -> &'static str {
+        match self {
+            GameLobbyRequest::UserLogin { .. } => "UserLogin",
+            GameLobbyRequest::HostNewGame { .. } => "HostNewGame",
+            GameLobbyRequest::HostStartGame { .. } => "HostStartGame",
+            GameLobbyRequest::HostRestartGame { .. } => "HostRestartGame",
+            GameLobbyRequest::HostEndGame { .. } => "HostEndGame",
+            GameLobbyRequest::ListQuestionPacks => "ListQuestionPacks",
+            GameLobbyRequest::Resume { .. } => "Resume",
+            GameLobbyRequest::GameAction { .. } => "GameAction",
         }
     }
 }
@@ -88,12 +324,397 @@ impl Distribution<RPSHand> for Standard {
 enum UserType {
     Host,
     Player,
+    /// Spectators follow along with the game without playing in it
+    Audience,
+}
+
+impl UserType {
+    fn is_player(&self) -> bool {
+        !matches!(self, UserType::Audience)
+    }
+}
+
+/// A player's running tally for the current match
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+struct PlayerScore {
+    wins: usize,
+    losses: usize,
+    draws: usize,
+    /// For games that score more than wins and losses, like trivia
+    points: usize,
+}
+
+impl PlayerScore {
+    fn record(&mut self, outcome: &RoundOutcome) {
+        match outcome {
+            RoundOutcome::Win => self.wins += 1,
+            RoundOutcome::Lose => self.losses += 1,
+            RoundOutcome::Draw => self.draws += 1,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct PlayerScoreEntry {
+    user_name: String,
+    rounds_played: usize,
+    score: PlayerScore,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct PlayerStanding {
+    rank: usize,
+    user_name: String,
+    score: PlayerScore,
+}
+
+#[derive(Debug)]
 struct GameLobbyState {
     game_started: bool,
+    game_over: bool,
+    /// Number of rounds each player gets in a match
+    total_rounds: usize,
+    /// The round being played, starting from 1 once the game has started
+    current_round: usize,
     users: Vec<UserServerSideState>,
+    /// Time players get each round, if rounds are timed
+    round_time_limit: Option<Duration>,
+    /// The moves the room plays with
+    rule_set: RuleSet,
+    /// What the host asked for when they last started a game
+    settings: GameSettings,
+    /// The game being played, once the host has started one. It's only taken
+    /// out while it's busy with the room, see `with_game`.
+    game: Option<Box<dyn Game>>,
+    /// Read-only overlay sockets following the room, by connection id. These
+    /// aren't users and don't keep the room alive.
+    overlays: HashMap<usize, mpsc::UnboundedSender<Result<Message, warp::Error>>>,
+    /// Most players the room takes, counting disconnected players holding a spot
+    max_players: usize,
+    /// Players can join while a game is being played
+    allow_late_join: bool,
+    /// Last time someone in the room did something, used to expire idle rooms
+    last_activity: Instant,
+    /// The room has been warned that it is about to expire
+    expiry_warned: bool,
+    /// When the host lost their connection, while they haven't come back
+    host_left: Option<Instant>,
+    /// The players' throws so far, for the server to play against
+    throw_history: ThrowHistory,
+}
+
+impl Default for GameLobbyState {
+    fn default() -> Self {
+        GameLobbyState {
+            game_started: false,
+            game_over: false,
+            total_rounds: DEFAULT_ROUNDS,
+            current_round: 0,
+            users: Vec::new(),
+            round_time_limit: None,
+            rule_set: RuleSet::default(),
+            settings: GameSettings::default(),
+            game: None,
+            overlays: HashMap::new(),
+            max_players: config::DEFAULT_MAX_PLAYERS_PER_ROOM,
+            allow_late_join: false,
+            last_activity: Instant::now(),
+            expiry_warned: false,
+            host_left: None,
+            throw_history: ThrowHistory::default(),
+        }
+    }
+}
+
+impl GameLobbyState {
+    /// Everyone in the room taking part in the game, leaving out the audience
+    fn players(&self) -> impl Iterator<Item = &UserServerSideState> {
+        self.users.iter().filter(|u| u.user_type.is_player())
+    }
+
+    /// Record activity in the room, holding off expiry
+    fn touch(&mut self) {
+        self.last_activity = Instant::now();
+        self.expiry_warned = false;
+    }
+
+    fn user_by_id(&self, user_id: usize) -> Option<&UserServerSideState> {
+        self.users.iter().find(|u| u.user_id == user_id)
+    }
+
+    fn name_taken(&self, user_name: &str) -> bool {
+        self.players()
+            .any(|u| u.user_name.to_lowercase() == user_name.to_lowercase())
+    }
+
+    /// Player names are unique within a room, ignoring case. Disconnected
+    /// players keep their name so they can resume their session. The audience
+    /// isn't shown by name, so they can share names.
+    fn check_user_name(&self, user_name: &str) -> Result<(), GameLobbyError> {
+        if !self.name_taken(user_name) {
+            return Ok(());
+        }
+
+        let suggested_name = (2..100)
+            .map(|n| {
+                let suffix = n.to_string();
+                let base: String = user_name
+                    .chars()
+                    .take(MAX_USER_NAME_LEN - suffix.len())
+                    .collect();
+                format!("{}{}", base, suffix)
+            })
+            .find(|candidate| !self.name_taken(candidate));
+
+        Err(GameLobbyError::new(
+            ErrorCode::NameTaken,
+            format!("Someone in the room is already called {}", user_name),
+        )
+        .with_suggested_name(suggested_name))
+    }
+
+    /// The audience can always come watch
+    fn check_can_join(&self, user_type: &UserType) -> Result<(), GameLobbyError> {
+        if !user_type.is_player() {
+            return Ok(());
+        }
+
+        if self.players().count() >= self.max_players {
+            return Err(GameLobbyError::new(
+                ErrorCode::RoomFull,
+                format!("The room is full ({} players)", self.max_players),
+            ));
+        }
+
+        if self.game_started && !self.game_over && !self.allow_late_join {
+            return Err(GameLobbyError::new(
+                ErrorCode::GameAlreadyStarted,
+                "The game has already started, wait for the next one".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Only the room's host is allowed to start, restart or end games
+    fn check_host(&self, user_id: usize, room_code: &str) -> Result<(), GameLobbyError> {
+        match self.user_by_id(user_id) {
+            Some(u) if matches!(u.user_type, UserType::Host) => Ok(()),
+            _ => Err(GameLobbyError::new(
+                ErrorCode::NotHost,
+                format!("Only the host of room {} can do that", room_code),
+            )),
+        }
+    }
+
+    /// Clear out scores and round counters for a fresh match with the same party
+    fn reset_match(&mut self, total_rounds: usize) {
+        self.game_started = true;
+        self.game_over = false;
+        self.total_rounds = total_rounds;
+        self.current_round = 1;
+
+        for u in self.users.iter_mut() {
+            u.score = PlayerScore::default();
+            u.rounds_played = 0;
+        }
+    }
+
+    /// Reset the match and hand the room over to a fresh game
+    fn begin_match(&mut self, room_code: &str, total_rounds: usize) {
+        self.touch();
+        self.reset_match(total_rounds);
+
+        self.game = Some(self.settings.new_game(&self.rule_set));
+        self.with_game(room_code, |game, room| game.start(room, room_code));
+    }
+
+    /// Let the game get on with something in the room. Once the game says
+    /// it's finished, the room announces the winners.
+    fn with_game<T, F>(&mut self, room_code: &str, f: F) -> Option<T>
+    where
+        F: FnOnce(&mut dyn Game, &mut GameLobbyState) -> T,
+    {
+        let mut game = self.game.take()?;
+        let result = f(game.as_mut(), self);
+        let finished = game.is_finished();
+        self.game = Some(game);
+
+        if finished && !self.game_over {
+            self.finish_match(room_code);
+        }
+
+        Some(result)
+    }
+
+    fn player_index(&self, user_name: &str) -> Option<usize> {
+        self.users
+            .iter()
+            .position(|u| u.user_type.is_player() && u.user_name == user_name)
+    }
+
+    fn game_start_update(&self, room_code: &str) -> GameLobbyResponse {
+        GameLobbyResponse::GameStart {
+            room_code: room_code.to_string(),
+            total_rounds: self.total_rounds,
+            settings: self.settings.clone(),
+        }
+    }
+
+    /// Everyone has completed their turns. Announce winner(s)
+    fn finish_match(&mut self, room_code: &str) {
+        self.game_over = true;
+        self.broadcast(&self.score_update(room_code));
+
+        let resp = self.game_over_update(room_code);
+        if let GameLobbyResponse::GameOver { winners, .. } = &resp {
+            info!("({}) Game over, winner(s): {:?}", room_code, winners);
+        }
+
+        self.broadcast(&resp);
+    }
+
+    fn game_over_update(&self, room_code: &str) -> GameLobbyResponse {
+        let standings = match self.game.as_ref() {
+            Some(game) => game.standings(self),
+            None => self.standings(),
+        };
+        let winners = standings
+            .iter()
+            .filter(|s| s.rank == 1)
+            .map(|s| s.user_name.clone())
+            .collect();
+
+        GameLobbyResponse::GameOver {
+            room_code: room_code.to_string(),
+            winners,
+            standings,
+        }
+    }
+
+    /// Bring a single user up to speed on the game being played in the room.
+    /// The party list is left to the next `PartyUpdate` broadcast.
+    fn replay_state(
+        &self,
+        room_code: &str,
+        tx: &mpsc::UnboundedSender<Result<Message, warp::Error>>,
+    ) {
+        if self.game_started {
+            send_response(tx, &self.game_start_update(room_code));
+            send_response(tx, &self.score_update(room_code));
+        }
+
+        if let Some(game) = self.game.as_ref() {
+            for event in game.snapshot(self) {
+                send_response(tx, &self.game_event(room_code, event));
+            }
+        }
+
+        if self.game_over {
+            send_response(tx, &self.game_over_update(room_code));
+        }
+    }
+
+    /// The users still connected to the room
+    fn party_update(&self, room_code: &str) -> GameLobbyResponse {
+        GameLobbyResponse::PartyUpdate {
+            room_code: room_code.to_string(),
+            users: self
+                .players()
+                .filter(|u| u.connected)
+                .map(|u| u.user_name.clone())
+                .collect(),
+            max_players: self.max_players,
+            audience_count: self
+                .users
+                .iter()
+                .filter(|u| u.connected && !u.user_type.is_player())
+                .count(),
+        }
+    }
+
+    fn score_update(&self, room_code: &str) -> GameLobbyResponse {
+        GameLobbyResponse::ScoreUpdate {
+            room_code: room_code.to_string(),
+            round: self.current_round,
+            total_rounds: self.total_rounds,
+            scores: self
+                .players()
+                .map(|u| PlayerScoreEntry {
+                    user_name: u.user_name.clone(),
+                    rounds_played: u.rounds_played,
+                    score: u.score.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Rank players by points, then wins, then by fewest losses. Tied players
+    /// share a rank.
+    fn standings(&self) -> Vec<PlayerStanding> {
+        let order = |a: &PlayerScore, b: &PlayerScore| {
+            b.points
+                .cmp(&a.points)
+                .then(b.wins.cmp(&a.wins))
+                .then(a.losses.cmp(&b.losses))
+        };
+
+        let mut ranked: Vec<&UserServerSideState> = self.players().collect();
+        ranked.sort_by(|a, b| order(&a.score, &b.score));
+
+        let mut standings: Vec<PlayerStanding> = Vec::with_capacity(ranked.len());
+        for (i, u) in ranked.iter().enumerate() {
+            let rank = match standings.last() {
+                Some(prev) if order(&prev.score, &u.score) == std::cmp::Ordering::Equal => {
+                    prev.rank
+                }
+                _ => i + 1,
+            };
+
+            standings.push(PlayerStanding {
+                rank,
+                user_name: u.user_name.clone(),
+                score: u.score.clone(),
+            });
+        }
+
+        standings
+    }
+
+    /// Send a response to every user in the room that still has a channel, and
+    /// to any overlays watching it
+    fn broadcast(&self, resp: &GameLobbyResponse) {
+        for u in self.users.iter() {
+            if let Some(tx) = u.channel.as_ref() {
+                send_response(tx, resp);
+            }
+        }
+
+        for tx in self.overlays.values() {
+            send_response(tx, resp);
+        }
+    }
+
+    fn game_event<E: Into<GameEvent>>(&self, room_code: &str, event: E) -> GameLobbyResponse {
+        GameLobbyResponse::GameEvent {
+            room_code: room_code.to_string(),
+            event: event.into(),
+        }
+    }
+
+    fn broadcast_event<E: Into<GameEvent>>(&self, room_code: &str, event: E) {
+        self.broadcast(&self.game_event(room_code, event));
+    }
+
+    /// Let everyone know the room is gone, once it's been taken out of `Games`.
+    /// Overlays have nothing left to follow, so their sockets are closed too.
+    fn close(self, room_code: String, reason: RoomClosedReason) {
+        self.broadcast(&GameLobbyResponse::RoomClosed { room_code, reason });
+
+        for tx in self.overlays.values() {
+            if let Err(_disconnected) = tx.send(Ok(Message::close())) {}
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -103,21 +724,83 @@ struct UserServerSideState {
     user_type: UserType,
     connected: bool,
     channel: Option<mpsc::UnboundedSender<Result<Message, warp::Error>>>,
-    // score: usize,
+    /// Lets a user reattach to this state from a new websocket
+    session_token: String,
+    score: PlayerScore,
+    rounds_played: usize,
+}
+
+impl UserServerSideState {
+    fn new(
+        user_id: usize,
+        user_name: String,
+        user_type: UserType,
+        channel: mpsc::UnboundedSender<Result<Message, warp::Error>>,
+    ) -> Self {
+        UserServerSideState {
+            user_id,
+            user_name,
+            user_type,
+            connected: true,
+            channel: Some(channel),
+            session_token: generate_session_token(),
+            score: PlayerScore::default(),
+            rounds_played: 0,
+        }
+    }
+
+    fn session_started(&self, room_code: &str, rule_set: &RuleSet) -> GameLobbyResponse {
+        GameLobbyResponse::SessionStarted {
+            room_code: room_code.to_string(),
+            user_name: self.user_name.clone(),
+            user_type: self.user_type.clone(),
+            session_token: self.session_token.clone(),
+            rule_set: rule_set.clone(),
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    pretty_env_logger::init();
+    let config = match ServerConfig::load() {
+        Ok(config) => Config::new(config),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    match config.log_level.as_ref() {
+        Some(filters) => pretty_env_logger::formatted_builder()
+            .parse_filters(filters)
+            .init(),
+        None => pretty_env_logger::init(),
+    }
 
     // Keep track of all connected users, key is usize, value
     // is a websocket sender.
     let users = Users::default();
     let games = Games::default();
+
+    tokio::task::spawn(room_sweeper(games.clone(), config.clone()));
+    tokio::task::spawn(round_clock(games.clone()));
+
+    if let Some(irc) = config.irc.clone() {
+        tokio::task::spawn(irc::run(irc, config.clone(), games.clone(), users.clone()));
+    }
+
+    let listen_addr = config.listen_addr;
+    let static_files = config.static_dir.clone();
+    let shutdown = shutdown(users.clone(), config.shutdown_grace);
+
     // Turn our "state" into a new Filter...
     let users = warp::any().map(move || users.clone());
+    let overlay_users = users.clone();
 
     let games = warp::any().map(move || games.clone());
+    let overlay_games = games.clone();
+
+    let config = warp::any().map(move || config.clone());
 
     // GET /ws -> websocket upgrade
     let ws = warp::path("ws")
@@ -125,21 +808,88 @@ async fn main() {
         .and(warp::ws())
         .and(users)
         .and(games)
-        .map(|ws: warp::ws::Ws, users, games| {
+        .and(config)
+        .map(|ws: warp::ws::Ws, users, games, config| {
             // This will call our function if the handshake succeeds.
-            ws.on_upgrade(move |socket| user_connected(socket, users, games))
+            ws.on_upgrade(move |socket| user_connected(socket, users, games, config))
+        });
+
+    // GET /overlay/:room_code -> read-only websocket for stream overlays
+    let overlay = warp::path!("overlay" / String)
+        .and(warp::ws())
+        .and(overlay_users)
+        .and(overlay_games)
+        .map(|room_code: String, ws: warp::ws::Ws, users, games| {
+            ws.on_upgrade(move |socket| overlay_connected(socket, room_code, users, games))
         });
 
     // GET / -> index html
-    let index = warp::path::end().and(warp::fs::dir("static"));
-    let static_dir = warp::path("static").and(warp::fs::dir("static"));
+    let index = warp::path::end().and(warp::fs::dir(static_files.clone()));
+    let static_dir = warp::path("static").and(warp::fs::dir(static_files));
 
-    let routes = index.or(ws).or(static_dir);
+    let routes = index.or(ws).or(overlay).or(static_dir);
 
-    warp::serve(routes).run(([127, 0, 0, 1], 3030)).await;
+    let (_, server) = warp::serve(routes).bind_with_graceful_shutdown(listen_addr, shutdown);
+    server.await;
 }
 
-async fn user_connected(ws: WebSocket, users: Users, games: Games) {
+/// Resolves once we get SIGINT or SIGTERM
+async fn shutdown_signal() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            eprintln!("Unable to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate.recv() => {},
+    }
+}
+
+/// Wait to be told to stop, then give everyone connected some warning before
+/// closing their sockets
+async fn shutdown(users: Users, grace: Duration) {
+    shutdown_signal().await;
+
+    SHUTTING_DOWN.store(true, Ordering::Relaxed);
+    info!("Shutting down in {} seconds", grace.as_secs());
+
+    let resp = GameLobbyResponse::ServerShuttingDown {
+        seconds: grace.as_secs(),
+    };
+    for tx in users.read().await.values() {
+        send_response(tx, &resp);
+    }
+
+    tokio::time::delay_for(grace).await;
+
+    info!("Closing all connections");
+    for tx in users.read().await.values() {
+        if let Err(_disconnected) = tx.send(Ok(Message::close())) {}
+    }
+
+    // Everyone leaves `users` once their socket has closed. Don't let the
+    // runtime drop their close frames on the way out.
+    let closed = tokio::time::timeout(SHUTDOWN_CLOSE_TIMEOUT, async {
+        while !users.read().await.is_empty() {
+            tokio::time::delay_for(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+
+    if closed.is_err() {
+        eprintln!(
+            "{} connection(s) didn't close in time",
+            users.read().await.len()
+        );
+    }
+}
+
+async fn user_connected(ws: WebSocket, users: Users, games: Games, config: Config) {
     // Use a counter to assign a new unique ID for this user.
     let my_id = NEXT_USER_ID.fetch_add(1, Ordering::Relaxed);
 
@@ -178,122 +928,43 @@ async fn user_connected(ws: WebSocket, users: Users, games: Games) {
             }
         };
 
-        // Pattern match a user logging in vs a host wanting a new game
-        // Skip any non-Text messages...
-        if let Ok(s) = msg.to_str() {
-            match serde_json::from_str(s).unwrap() {
-                GameLobbyRequest::HostNewGame {
-                    user_name,
-                    user_type,
-                } => {
-                    // Generate a room code
-                    // Add host to game
-
-                    //let mut game_lobby_state = GameLobbyState::default();
-                    let room_code = generate_room_code();
-
-                    games
-                        .write()
-                        .await
-                        .entry(room_code.clone())
-                        .or_insert(GameLobbyState::default());
-
-                    games
-                        .write()
-                        .await
-                        .entry(room_code.clone())
-                        .and_modify(|e| {
-                            e.users.push(UserServerSideState {
-                                user_id: my_id,
-                                user_name: user_name,
-                                user_type: user_type,
-                                connected: true,
-                                channel: Some(tx.clone()),
-                            })
-                        });
-
-                    info!("New host creating game. Room code: {}", &room_code);
-
-                    let msg = format!("Room code is: {}", &room_code);
-                    if let Err(_disconnected) = tx.send(Ok(Message::text(&msg))) {
-                        // The tx is disconnected, our `user_disconnected` code
-                        // should be happening in another task, nothing more to
-                        // do here.
-                    }
-
-                    info!("Host joining game lobby");
-
-                    //game_lobby(my_id, login_info, &users, &games).await
-                    game_lobby(my_id, &games, &room_code).await
-                    // start_game()
-                }
-
-                GameLobbyRequest::UserLogin {
-                    user_name,
-                    user_type,
-                    room_code,
-                } => {
-                    info!("New user joining room {}", &room_code.to_uppercase());
-
-                    // Check for the existence of the room code in games
-                    // if it doesn't exist, then send a message back to the user and then close the channel...
-                    if !games.read().await.contains_key(&room_code.to_uppercase()) {
-                        let error_msg =
-                            format!("Room code: {} does not exist", &room_code.to_uppercase());
-
-                        eprintln!("{}", &error_msg);
-
-                        if let Err(_disconnected) = tx.send(Ok(Message::text(&error_msg))) {
-                            // The tx is disconnected, our `user_disconnected` code
-                            // should be happening in another task, nothing more to
-                            // do here.
-                        }
-
-                        break;
-                    } else {
-                        // otherwise, add the user to the game room
-
-                        info!("Adding new user into game room");
-
-                        games
-                            .write()
-                            .await
-                            .entry(room_code.clone())
-                            .and_modify(|e| {
-                                e.users.push(UserServerSideState {
-                                    user_id: my_id,
-                                    user_name: user_name,
-                                    user_type: user_type,
-                                    connected: true,
-                                    channel: Some(tx.clone()),
-                                })
-                            });
-                    }
+        if msg.is_close() {
+            break;
+        }
 
-                    info!("Joining the game lobby");
+        // Skip any control messages, warp takes care of answering pings
+        if msg.is_ping() || msg.is_pong() {
+            continue;
+        }
 
-                    //game_lobby(my_id, login_info, &users, &games).await
-                    game_lobby(my_id, &games, &room_code).await
-                    // start_game()
-                }
+        let s = match msg.to_str() {
+            Ok(s) => s,
+            Err(_) => {
+                let err = GameLobbyError::new(
+                    ErrorCode::BadRequest,
+                    "Only text messages are supported".to_string(),
+                );
+                send_response(&tx, &err.into_response(None));
+                continue;
+            }
+        };
 
-                GameLobbyRequest::HostStartGame { room_code } => {
-                    println!("Start game for room: {:?}", &room_code);
-                    game_start(&games, room_code).await
-                }
+        let req: GameLobbyRequest = match serde_json::from_str(s) {
+            Ok(req) => req,
+            Err(e) => {
+                eprintln!("Bad request from user {}: {}", my_id, e);
 
-                GameLobbyRequest::PlayerHand {
-                    user_name,
-                    room_code,
-                    hand,
-                } => {
-                    println!("({}) {} played hand: {:?}", room_code, user_name, hand);
-                    server_play_hand(&games, room_code, user_name).await
-                }
+                let err = GameLobbyError::new(ErrorCode::BadRequest, e.to_string());
+                send_response(&tx, &err.into_response(None));
+                continue;
             }
-        } else {
-            return;
         };
+
+        let request = req.kind();
+        if let Err(err) = handle_request(my_id, &tx, &games, &config, req).await {
+            eprintln!("{} failed for user {}: {}", request, my_id, err.message);
+            send_response(&tx, &err.into_response(Some(request)));
+        }
     }
 
     // user_ws_rx stream will keep processing as long as the user stays
@@ -301,108 +972,504 @@ async fn user_connected(ws: WebSocket, users: Users, games: Games) {
     user_disconnected(my_id, &users2, &games2).await;
 }
 
-async fn server_play_hand(games: &Games, room_code: String, user_name: String) {
-    let random_hand: RPSHand = rand::random();
+/// Follow a room for a stream overlay. Overlays only listen: they get
+/// everything broadcast to the room, but never join it.
+async fn overlay_connected(ws: WebSocket, room_code: String, users: Users, games: Games) {
+    let my_id = NEXT_USER_ID.fetch_add(1, Ordering::Relaxed);
+    let room_code = room_code.to_uppercase();
 
-    for (room, game_state) in games.read().await.iter() {
-        let all_users: Vec<String> = game_state
-            .users
-            .iter()
-            .map(|u| u.user_name.clone())
-            .collect();
+    let (overlay_ws_tx, mut overlay_ws_rx) = ws.split();
 
-        if room == &room_code {
-            eprintln!("Hopefully about to print to all users in room?");
-            for u in game_state.users.iter() {
-                if u.user_name == user_name {
-                    eprintln!("User: {:?}", u);
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::task::spawn(rx.forward(overlay_ws_tx).map(|result| {
+        if let Err(e) = result {
+            eprintln!("websocket send error: {}", e);
+        }
+    }));
 
-                    let resp = GameLobbyResponse::ServerHand {
-                        hand: random_hand.clone(),
-                    };
+    {
+        let mut games = games.write().await;
 
-                    let msg = format!("{}", json!(resp).to_string());
+        let game_state = match games.get_mut(&room_code) {
+            Some(game_state) => game_state,
+            None => {
+                let err = GameLobbyError::unknown_room(&room_code);
+                send_response(&tx, &err.into_response(None));
+                if let Err(_disconnected) = tx.send(Ok(Message::close())) {}
+                return;
+            }
+        };
 
-                    if let Some(tx) = u.channel.clone() {
-                        if let Err(_disconnected) = tx.send(Ok(Message::text(msg))) {}
-                    }
-                }
+        info!("({}) Overlay {} connected", room_code, my_id);
+
+        send_response(&tx, &game_state.party_update(&room_code));
+        game_state.replay_state(&room_code, &tx);
+        game_state.overlays.insert(my_id, tx.clone());
+    }
+
+    // Only so shutdown can warn the overlay and close it
+    users.write().await.insert(my_id, tx);
+
+    // Nothing an overlay sends means anything to us, just wait for it to go away
+    while let Some(result) = overlay_ws_rx.next().await {
+        match result {
+            Ok(msg) if msg.is_close() => break,
+            Ok(_) => continue,
+            Err(e) => {
+                eprintln!("websocket error(overlay={}): {}", my_id, e);
+                break;
             }
         }
     }
+
+    users.write().await.remove(&my_id);
+
+    if let Some(game_state) = games.write().await.get_mut(&room_code) {
+        info!("({}) Overlay {} disconnected", room_code, my_id);
+        game_state.overlays.remove(&my_id);
+    }
 }
 
-async fn game_start(games: &Games, room_code: String) {
-    games
-        .write()
-        .await
-        .entry(room_code.clone())
-        .and_modify(|e| {
-            e.game_started = true;
-        });
+async fn handle_request(
+    my_id: usize,
+    tx: &mpsc::UnboundedSender<Result<Message, warp::Error>>,
+    games: &Games,
+    config: &ServerConfig,
+    req: GameLobbyRequest,
+) -> Result<(), GameLobbyError> {
+    // Pattern match a user logging in vs a host wanting a new game
+    match req {
+        GameLobbyRequest::HostNewGame {
+            user_name,
+            user_type: _,
+            max_players,
+            allow_late_join,
+            rule_set,
+        } => {
+            if let Some(room_code) = bound_room(my_id, games).await {
+                return Err(GameLobbyError::already_in_room(&room_code));
+            }
 
-    for (room, game_state) in games.read().await.iter() {
-        let all_users: Vec<String> = game_state
-            .users
-            .iter()
-            .map(|u| u.user_name.clone())
-            .collect();
+            if SHUTTING_DOWN.load(Ordering::Relaxed) {
+                return Err(GameLobbyError::new(
+                    ErrorCode::ShuttingDown,
+                    "The server is shutting down, try again in a bit".to_string(),
+                ));
+            }
 
-        if room == &room_code {
-            eprintln!("Hopefully about to print to all users in room?");
-            for u in game_state.users.iter() {
-                eprintln!("User: {:?}", u);
+            let user_name = validate_user_name(&user_name)?;
+            let rule_set = RuleSet::new(rule_set)
+                .map_err(|e| GameLobbyError::new(ErrorCode::InvalidRuleSet, e.to_string()))?;
 
-                let resp = GameLobbyResponse::GameStart {
-                    room_code: room_code.clone(),
+            // Generate a room code and add host to game, holding the lock so
+            // nobody else can grab the same code in between
+            let room_code = {
+                let mut games = games.write().await;
+
+                if games.len() >= config.max_rooms {
+                    return Err(GameLobbyError::new(
+                        ErrorCode::NoRoomsAvailable,
+                        "The server is full, try again later".to_string(),
+                    ));
+                }
+
+                let room_code = config
+                    .room_codes
+                    .allocate(&games)
+                    .map_err(|e| GameLobbyError::new(ErrorCode::NoRoomsAvailable, e.to_string()))?;
+
+                // Whoever creates the room hosts it, no matter what they claim to be
+                let host = UserServerSideState::new(my_id, user_name, UserType::Host, tx.clone());
+                send_response(tx, &host.session_started(&room_code, &rule_set));
+
+                let mut game_state = GameLobbyState {
+                    max_players: max_players
+                        .unwrap_or(config.max_players_per_room)
+                        .clamp(1, config.max_players_per_room),
+                    allow_late_join,
+                    round_time_limit: config.round_time_limit,
+                    rule_set,
+                    ..GameLobbyState::default()
                 };
+                game_state.users.push(host);
+                games.insert(room_code.clone(), game_state);
+
+                room_code
+            };
+
+            info!("New host creating game. Room code: {}", &room_code);
 
-                let msg = format!("{}", json!(resp).to_string());
+            info!("Host joining game lobby");
 
-                if let Some(tx) = u.channel.clone() {
-                    if let Err(_disconnected) = tx.send(Ok(Message::text(msg))) {}
+            //game_lobby(my_id, login_info, &users, &games).await
+            game_lobby(my_id, games, &room_code).await;
+            // start_game()
+        }
+
+        GameLobbyRequest::UserLogin {
+            user_name,
+            user_type,
+            room_code,
+        } => {
+            let room_code = room_code.to_uppercase();
+            info!("New user joining room {}", &room_code);
+
+            if let Some(room_code) = bound_room(my_id, games).await {
+                return Err(GameLobbyError::already_in_room(&room_code));
+            }
+
+            // Rooms only get a host through HostNewGame
+            if matches!(user_type, UserType::Host) {
+                return Err(GameLobbyError::new(
+                    ErrorCode::NotHost,
+                    "Join a room as a player, or create a new room to host".to_string(),
+                ));
+            }
+
+            let user_name = validate_user_name(&user_name)?;
+
+            // Check for the existence of the room code in games
+            // if it doesn't exist, then send an error back to the user
+            match games.write().await.get_mut(&room_code) {
+                Some(game_state) => {
+                    game_state.check_can_join(&user_type)?;
+                    if user_type.is_player() {
+                        game_state.check_user_name(&user_name)?;
+                    }
+
+                    // otherwise, add the user to the game room
+                    info!("Adding new user into game room");
+
+                    let mut user =
+                        UserServerSideState::new(my_id, user_name, user_type, tx.clone());
+                    send_response(tx, &user.session_started(&room_code, &game_state.rule_set));
+
+                    // Late joiners sit out the rounds they missed
+                    if game_state.game_started && !game_state.game_over {
+                        user.rounds_played = game_state.current_round - 1;
+                    }
+
+                    game_state.replay_state(&room_code, tx);
+                    game_state.users.push(user);
+                    game_state.touch();
                 }
+                None => return Err(GameLobbyError::unknown_room(&room_code)),
             }
+
+            info!("Joining the game lobby");
+
+            //game_lobby(my_id, login_info, &users, &games).await
+            game_lobby(my_id, games, &room_code).await;
+            // start_game()
+        }
+
+        GameLobbyRequest::HostStartGame {
+            room_code,
+            rounds,
+            game,
+        } => {
+            println!("Start game for room: {:?}", &room_code);
+            game_start(my_id, games, config, room_code, rounds, game).await?;
+        }
+
+        GameLobbyRequest::HostRestartGame { room_code } => {
+            println!("Restart game for room: {:?}", &room_code);
+            game_restart(my_id, games, room_code).await?;
+        }
+
+        GameLobbyRequest::HostEndGame { room_code } => {
+            println!("End game for room: {:?}", &room_code);
+            game_end(my_id, games, room_code).await?;
+        }
+
+        GameLobbyRequest::GameAction { room_code, action } => {
+            game_action(my_id, games, room_code, action).await?;
         }
+
+        GameLobbyRequest::ListQuestionPacks => {
+            let packs = QuestionPack::list(&config.question_pack_dir).await;
+            send_response(tx, &GameLobbyResponse::QuestionPacks { packs });
+        }
+
+        GameLobbyRequest::Resume { token } => {
+            if let Some(room_code) = bound_room(my_id, games).await {
+                return Err(GameLobbyError::already_in_room(&room_code));
+            }
+
+            let room_code = user_resume(my_id, tx, games, &token).await?;
+            game_lobby(my_id, games, &room_code).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Pass something a user did in the game along to the room's game
+async fn game_action(
+    my_id: usize,
+    games: &Games,
+    room_code: String,
+    action: GameAction,
+) -> Result<(), GameLobbyError> {
+    let mut games = games.write().await;
+
+    let game_state = games
+        .get_mut(&room_code)
+        .ok_or_else(|| GameLobbyError::unknown_room(&room_code))?;
+
+    let user_index = game_state
+        .users
+        .iter()
+        .position(|u| u.user_id == my_id)
+        .ok_or_else(|| {
+            GameLobbyError::new(ErrorCode::NotInRoom, format!("Not in room {}", room_code))
+        })?;
+
+    if !game_state.game_started || game_state.game_over {
+        return Err(GameLobbyError::new(
+            ErrorCode::GameNotStarted,
+            format!("Room code: {} has no game in progress", room_code),
+        ));
+    }
+
+    game_state.touch();
+    game_state
+        .with_game(&room_code, |game, room| {
+            game.handle_action(room, &room_code, user_index, action)
+        })
+        .unwrap_or_else(|| {
+            Err(GameLobbyError::new(
+                ErrorCode::GameNotStarted,
+                format!("Room code: {} has no game in progress", room_code),
+            ))
+        })
+}
+
+/// Start the game the host picked, `rounds` long if the game plays in rounds
+async fn game_start(
+    my_id: usize,
+    games: &Games,
+    config: &ServerConfig,
+    room_code: String,
+    rounds: Option<usize>,
+    settings: GameSettings,
+) -> Result<(), GameLobbyError> {
+    // Trivia reads its questions from disk, which every room shouldn't have
+    // to wait on
+    let settings = settings.prepare(config).await?;
+
+    let mut games = games.write().await;
+
+    let game_state = games
+        .get_mut(&room_code)
+        .ok_or_else(|| GameLobbyError::unknown_room(&room_code))?;
+
+    game_state.check_host(my_id, &room_code)?;
+
+    if game_state.game_started && !game_state.game_over {
+        return Err(GameLobbyError::new(
+            ErrorCode::GameAlreadyStarted,
+            format!("Room code: {} already has a game in progress", room_code),
+        ));
+    }
+
+    game_state.settings = settings;
+    game_state.begin_match(
+        &room_code,
+        rounds.unwrap_or(DEFAULT_ROUNDS).clamp(1, MAX_ROUNDS),
+    );
+
+    Ok(())
+}
+
+/// Run another match with the same party and number of rounds
+async fn game_restart(
+    my_id: usize,
+    games: &Games,
+    room_code: String,
+) -> Result<(), GameLobbyError> {
+    let mut games = games.write().await;
+
+    let game_state = games
+        .get_mut(&room_code)
+        .ok_or_else(|| GameLobbyError::unknown_room(&room_code))?;
+
+    game_state.check_host(my_id, &room_code)?;
+
+    if !game_state.game_started {
+        return Err(GameLobbyError::new(
+            ErrorCode::GameNotStarted,
+            format!("Room code: {} hasn't started a game yet", room_code),
+        ));
     }
 
-    // Humans vs the server
-    // Best out of 5
-    // Send updates to everyone on the number of turns taken + wins
+    let total_rounds = game_state.total_rounds;
+    game_state.begin_match(&room_code, total_rounds);
+
+    Ok(())
+}
+
+/// Dissolve the room and let everyone in it know
+async fn game_end(my_id: usize, games: &Games, room_code: String) -> Result<(), GameLobbyError> {
+    let mut games = games.write().await;
+
+    games
+        .get(&room_code)
+        .ok_or_else(|| GameLobbyError::unknown_room(&room_code))?
+        .check_host(my_id, &room_code)?;
+
+    let game_state = games
+        .remove(&room_code)
+        .ok_or_else(|| GameLobbyError::unknown_room(&room_code))?;
+
+    info!("({}) Host ended the game, closing room", room_code);
+
+    game_state.close(room_code, RoomClosedReason::HostEndedGame);
 
-    // When everyone has completed their turns. Announce winner(s), and allow the host to restart or end
+    Ok(())
 }
 
-async fn game_lobby(_my_id: usize, games: &Games, room_code: &String) {
+async fn game_lobby(_my_id: usize, games: &Games, room_code: &str) {
     // TODO: Remove this when I've got all the other login stuff established
     //let new_msg = format!("<User#{}>: ", my_id);
 
     //eprintln!("{}", &new_msg);
 
-    // loop over the game lobby's set of users and announce the current set of users
-    for (room, game_state) in games.read().await.iter() {
-        eprintln!("({}) Looping through room: {}", room_code, room);
+    // Announce the current set of users to everyone in the room
+    if let Some(game_state) = games.read().await.get(room_code) {
+        game_state.broadcast(&game_state.party_update(room_code));
+    }
+}
+
+/// The room this connection's user belongs to, if any
+async fn bound_room(my_id: usize, games: &Games) -> Option<String> {
+    games
+        .read()
+        .await
+        .iter()
+        .find(|(_, game_state)| game_state.user_by_id(my_id).is_some())
+        .map(|(room_code, _)| room_code.clone())
+}
 
-        let all_users: Vec<String> = game_state
+/// Reattach a new websocket to the user holding `token`, and replay the room to them.
+///
+/// Returns the room code the user was put back into.
+async fn user_resume(
+    my_id: usize,
+    tx: &mpsc::UnboundedSender<Result<Message, warp::Error>>,
+    games: &Games,
+    token: &str,
+) -> Result<String, GameLobbyError> {
+    let mut games = games.write().await;
+
+    for (room_code, game_state) in games.iter_mut() {
+        if let Some(user_index) = game_state
             .users
             .iter()
-            .map(|u| u.user_name.clone())
-            .collect();
+            .position(|u| u.session_token == token)
+        {
+            let u = &mut game_state.users[user_index];
+            info!("({}) {} resumed their session", room_code, u.user_name);
+
+            // Whoever still holds the session is pushed off it, instead of being
+            // left on a socket that no longer hears anything
+            if let Some(old_tx) = u.channel.take() {
+                let err = GameLobbyError::new(
+                    ErrorCode::InvalidSession,
+                    "This session was resumed from somewhere else".to_string(),
+                );
+                send_response(&old_tx, &err.into_response(None));
+                if let Err(_disconnected) = old_tx.send(Ok(Message::close())) {}
+            }
 
-        if room == room_code {
-            eprintln!("Hopefully about to print to all users in room?");
-            for u in game_state.users.iter() {
-                eprintln!("User: {:?}", u);
+            let host_back = matches!(u.user_type, UserType::Host);
 
-                let resp = GameLobbyResponse::PartyUpdate {
-                    room_code: room.to_string(),
-                    users: all_users.clone(),
-                };
-                let msg = format!("{}", json!(resp).to_string());
+            u.user_id = my_id;
+            u.connected = true;
+            u.channel = Some(tx.clone());
 
-                if let Some(tx) = u.channel.clone() {
-                    if let Err(_disconnected) = tx.send(Ok(Message::text(msg))) {}
-                }
+            send_response(tx, &u.session_started(room_code, &game_state.rule_set));
+
+            if host_back && game_state.host_left.take().is_some() {
+                game_state.broadcast(&GameLobbyResponse::HostBack {
+                    room_code: room_code.clone(),
+                });
+            }
+
+            if game_state.game_started
+                && !game_state.game_over
+                && game_state.users[user_index].user_type.is_player()
+            {
+                game_state.with_game(room_code, |game, room| {
+                    game.player_returned(room, room_code, user_index)
+                });
+            }
+            game_state.touch();
+            game_state.replay_state(room_code, tx);
+
+            return Ok(room_code.clone());
+        }
+    }
+
+    Err(GameLobbyError::new(
+        ErrorCode::InvalidSession,
+        "Session has expired or does not exist".to_string(),
+    ))
+}
+
+/// Settle rounds that have run out of time
+async fn round_clock(games: Games) {
+    let mut interval = tokio::time::interval(ROUND_CLOCK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let mut games = games.write().await;
+
+        for (room_code, game_state) in games.iter_mut() {
+            if game_state.game_started && !game_state.game_over {
+                game_state.with_game(room_code, |game, room| game.tick(room, room_code));
+            }
+        }
+    }
+}
+
+/// Background task that closes rooms nobody is using anymore, freeing up their
+/// room codes. Idle rooms get a warning first.
+async fn room_sweeper(games: Games, config: Config) {
+    let mut interval = tokio::time::interval(ROOM_SWEEP_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let mut games = games.write().await;
+        let now = Instant::now();
+
+        let mut closed = Vec::new();
+        for (room_code, game_state) in games.iter_mut() {
+            let idle = now.duration_since(game_state.last_activity);
+
+            // A room waiting on its host is closed by `close_if_host_gone`
+            if !game_state.users.iter().any(|u| u.connected) && game_state.host_left.is_none() {
+                closed.push((room_code.clone(), RoomClosedReason::Empty));
+            } else if idle >= config.room_idle_timeout {
+                closed.push((room_code.clone(), RoomClosedReason::Idle));
+            } else if idle + config.room_expiry_warning >= config.room_idle_timeout
+                && !game_state.expiry_warned
+            {
+                game_state.expiry_warned = true;
+                game_state.broadcast(&GameLobbyResponse::RoomExpiring {
+                    room_code: room_code.clone(),
+                    seconds_left: (config.room_idle_timeout - idle).as_secs(),
+                });
+            }
+        }
+
+        for (room_code, reason) in closed {
+            if let Some(game_state) = games.remove(&room_code) {
+                info!("({}) Closing room: {:?}", room_code, reason);
+
+                game_state.close(room_code, reason);
             }
         }
     }
@@ -411,19 +1478,130 @@ async fn game_lobby(_my_id: usize, games: &Games, room_code: &String) {
 async fn user_disconnected(my_id: usize, users: &Users, games: &Games) {
     eprintln!("good bye user: {}", my_id);
 
-    // If the user disconnected is a game host, close the streams for all users in the game
-    // Otherwise, go into game and change toggle connected state, drop channel, change id to 0
-
     // Stream closed up, so remove from the user list
     users.write().await.remove(&my_id);
+
+    let games_handle = games;
+    let mut games = games.write().await;
+
+    let room_code = match games
+        .iter()
+        .find(|(_, game_state)| game_state.users.iter().any(|u| u.user_id == my_id))
+    {
+        Some((room_code, _)) => room_code.clone(),
+        None => return,
+    };
+
+    // Go into game and toggle connected state, drop channel, change id to 0
+    if let Some(game_state) = games.get_mut(&room_code) {
+        let mut host_left = false;
+
+        if let Some(u) = game_state.users.iter_mut().find(|u| u.user_id == my_id) {
+            info!("({}) {} disconnected", room_code, u.user_name);
+
+            u.connected = false;
+            u.channel = None;
+            u.user_id = 0;
+            host_left = matches!(u.user_type, UserType::Host);
+        }
+
+        game_state.broadcast(&game_state.party_update(&room_code));
+
+        // The room can't go on without its host, but give them a chance to come back
+        if host_left {
+            let left_at = Instant::now();
+            game_state.host_left = Some(left_at);
+            game_state.broadcast(&GameLobbyResponse::HostAway {
+                room_code: room_code.clone(),
+                seconds_left: HOST_RECONNECT_GRACE.as_secs(),
+            });
+
+            tokio::task::spawn(close_if_host_gone(
+                games_handle.clone(),
+                room_code.clone(),
+                left_at,
+            ));
+        }
+
+        if game_state.game_started && !game_state.game_over {
+            game_state.with_game(&room_code, |game, room| game.player_left(room, &room_code));
+        }
+    }
+}
+
+/// Close the room if its host hasn't resumed their session since they left
+/// at `left_at`
+async fn close_if_host_gone(games: Games, room_code: String, left_at: Instant) {
+    tokio::time::delay_for(HOST_RECONNECT_GRACE).await;
+
+    let mut games = games.write().await;
+
+    let host_gone = games
+        .get(&room_code)
+        .is_some_and(|game_state| game_state.host_left == Some(left_at));
+
+    if host_gone {
+        if let Some(game_state) = games.remove(&room_code) {
+            info!("({}) Host didn't come back, closing room", room_code);
+
+            game_state.close(room_code, RoomClosedReason::HostDisconnected);
+        }
+    }
+}
+
+fn send_response(
+    tx: &mpsc::UnboundedSender<Result<Message, warp::Error>>,
+    resp: &GameLobbyResponse,
+) {
+    let msg = json!(resp).to_string();
+
+    if let Err(_disconnected) = tx.send(Ok(Message::text(msg))) {
+        // The tx is disconnected, our `user_disconnected` code
+        // should be happening in another task, nothing more to
+        // do here.
+    }
+}
+
+/// Trim the name and make sure it is something we can show on stream
+fn validate_user_name(user_name: &str) -> Result<String, GameLobbyError> {
+    let user_name = user_name.trim();
+    let len = user_name.chars().count();
+
+    if len == 0 || len > MAX_USER_NAME_LEN {
+        return Err(GameLobbyError::new(
+            ErrorCode::InvalidName,
+            format!(
+                "Names must be between 1 and {} characters long",
+                MAX_USER_NAME_LEN
+            ),
+        ));
+    }
+
+    let allowed = |c: char| c.is_ascii_alphanumeric() || c == ' ' || c == '_' || c == '-';
+
+    if !user_name.chars().all(allowed) {
+        let suggested_name: String = user_name.chars().filter(|c| allowed(*c)).collect();
+        let suggested_name = suggested_name.trim();
+
+        return Err(GameLobbyError::new(
+            ErrorCode::InvalidName,
+            "Names can only use letters, numbers, spaces, '_' and '-'".to_string(),
+        )
+        .with_suggested_name(if suggested_name.is_empty() {
+            None
+        } else {
+            Some(suggested_name.to_string())
+        }));
+    }
+
+    Ok(user_name.to_string())
 }
 
-pub fn generate_room_code() -> String {
+fn generate_session_token() -> String {
     let mut rng = thread_rng();
 
     iter::repeat(())
-        .map(|()| rng.sample(Alphanumeric).to_ascii_uppercase())
-        .filter(|c| c.is_alphabetic())
-        .take(4)
+        .map(|()| rng.sample(Alphanumeric))
+        .take(32)
         .collect()
 }
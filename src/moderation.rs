@@ -0,0 +1,54 @@
+//! Checks on what players put in front of everyone else.
+//!
+//! Anything players make up themselves, like the prompt game's submissions,
+//! goes past every `Moderator` the server runs before anyone else sees it.
+//! Whatever gets through can still be taken down by the room's host.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::prompt::Submission;
+
+pub trait Moderator: fmt::Debug + Send + Sync {
+    /// Turn a submission away by giving the reason to show its author
+    fn review(&self, submission: &Submission) -> Result<(), String>;
+}
+
+/// Turns away text using any of the blocked words, ignoring case. Only whole
+/// words count, so blocking "ass" doesn't take out "class".
+#[derive(Debug, Clone)]
+pub struct BlockedWords {
+    words: HashSet<String>,
+}
+
+impl BlockedWords {
+    pub fn new<I: IntoIterator<Item = String>>(words: I) -> Self {
+        BlockedWords {
+            words: words
+                .into_iter()
+                .map(|w| w.trim().to_lowercase())
+                .filter(|w| !w.is_empty())
+                .collect(),
+        }
+    }
+}
+
+impl Moderator for BlockedWords {
+    fn review(&self, submission: &Submission) -> Result<(), String> {
+        let text = match submission {
+            Submission::Text(text) => text,
+            // Nothing to read in a drawing, that's up to the host
+            Submission::Drawing(_) => return Ok(()),
+        };
+
+        let blocked = text
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| self.words.contains(&word.to_lowercase()));
+
+        if blocked {
+            Err("That uses a word that isn't allowed here".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
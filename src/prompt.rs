@@ -0,0 +1,778 @@
+//! The prompt game: everyone answers the same prompt, then votes on the best.
+//!
+//! Each round players get a prompt and a little time to answer it, with a
+//! line of text or a quick drawing. Once the answers are in they're shown to
+//! the room without their authors, and players and audience vote for their
+//! favourite. Every vote is worth points to the author, and authors are only
+//! revealed along with the votes.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::info;
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+
+use crate::config::ServerConfig;
+use crate::game::{self, Game, GameAction, GameEvent};
+use crate::moderation::Moderator;
+use crate::{ErrorCode, GameLobbyError, GameLobbyState, UserType};
+
+/// Time players get to answer when neither the host nor the room says
+const DEFAULT_SUBMIT_TIME: Duration = Duration::from_secs(60);
+/// Time everyone gets to vote when the host doesn't say
+const DEFAULT_VOTE_TIME: Duration = Duration::from_secs(30);
+/// How long the results stay up before the next prompt
+const RESULTS_TIME: Duration = Duration::from_secs(8);
+/// Shortest and longest the host can make submitting or voting, in seconds
+const MIN_PHASE_SECS: u64 = 5;
+const MAX_PHASE_SECS: u64 = 300;
+
+/// Points an author gets for every vote their submission gets
+const POINTS_PER_VOTE: usize = 100;
+
+/// Longest a text submission or a prompt can be, in characters
+pub const MAX_TEXT_LEN: usize = 140;
+/// Most prompts a host can bring
+pub const MAX_PROMPTS: usize = 100;
+/// Drawings are on a square canvas this many units across, whatever size
+/// they're shown at
+pub const CANVAS_SIZE: u16 = 500;
+pub const MAX_STROKE_WIDTH: u8 = 20;
+pub const MAX_STROKES: usize = 300;
+/// Most points across all of a drawing's strokes
+pub const MAX_DRAWING_POINTS: usize = 5000;
+
+/// Asked when the host doesn't bring their own prompts
+const PROMPTS: &[&str] = &[
+    "A new flavour of ice cream nobody asked for",
+    "The worst thing to hear from your pilot",
+    "A rejected superhero",
+    "What the cat is actually thinking",
+    "A terrible name for a boat",
+    "The next big social media app",
+    "Something you shouldn't bring to a job interview",
+    "An Olympic sport that should exist",
+    "What's really at the bottom of the ocean",
+    "A rule for the stream that chat would break instantly",
+    "The worst possible pizza topping",
+    "A wizard's least useful spell",
+    "The real reason the dinosaurs died out",
+    "A houseplant with a secret",
+    "What aliens would think of a shopping mall",
+    "A dance move named after a vegetable",
+    "The most suspicious thing to find in a fridge",
+    "A sequel nobody needed",
+    "A robot's favourite hobby",
+    "The last thing you'd want to find in your shoe",
+];
+
+/// What players can answer prompts with
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub enum SubmissionKind {
+    #[default]
+    Text,
+    Drawing,
+    /// Either one, player's choice
+    Either,
+}
+
+/// How the host set up the game
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PromptSettings {
+    pub submissions: SubmissionKind,
+    /// The host's own prompts, asked instead of the built-in ones
+    pub prompts: Vec<String>,
+    /// Seconds players get to answer each prompt, instead of the room's round time
+    pub submit_secs: Option<u64>,
+    /// Seconds everyone gets to vote
+    pub vote_secs: Option<u64>,
+    /// The server's moderators, which every submission has to get past
+    #[serde(skip)]
+    moderators: Vec<Arc<dyn Moderator>>,
+}
+
+impl PromptSettings {
+    /// Check the host's prompts, and bring in the server's moderators
+    pub fn prepare(mut self, config: &ServerConfig) -> Result<Self, GameLobbyError> {
+        let invalid = |message: String| GameLobbyError::new(ErrorCode::InvalidSettings, message);
+
+        if self.prompts.len() > MAX_PROMPTS {
+            return Err(invalid(format!("Bring at most {} prompts", MAX_PROMPTS)));
+        }
+
+        self.prompts = self
+            .prompts
+            .iter()
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect();
+
+        if let Some(prompt) = self
+            .prompts
+            .iter()
+            .find(|p| p.chars().count() > MAX_TEXT_LEN)
+        {
+            return Err(invalid(format!(
+                "Prompts can be at most {} characters: \"{}\"",
+                MAX_TEXT_LEN, prompt
+            )));
+        }
+
+        // Prompts get posted to chat, where a line break would start a new command
+        if self.prompts.iter().any(|p| p.chars().any(char::is_control)) {
+            return Err(invalid("Prompts can't have control characters".to_string()));
+        }
+
+        self.moderators = config.moderators.clone();
+        Ok(self)
+    }
+
+    fn phase_time(secs: Option<u64>, default: Duration) -> Duration {
+        secs.map(|secs| Duration::from_secs(secs.clamp(MIN_PHASE_SECS, MAX_PHASE_SECS)))
+            .unwrap_or(default)
+    }
+}
+
+/// One line of a drawing, in canvas units
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Stroke {
+    /// As "#rrggbb"
+    pub color: String,
+    pub width: u8,
+    /// (x, y) points along the stroke, from 0 to `CANVAS_SIZE`
+    pub points: Vec<(u16, u16)>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub enum Submission {
+    Text(String),
+    Drawing(Vec<Stroke>),
+}
+
+impl Submission {
+    /// Make sure the submission is the kind being asked for and fits on
+    /// screen, tidying up any text
+    fn check(self, kind: &SubmissionKind) -> Result<Submission, String> {
+        match self {
+            Submission::Text(_) if *kind == SubmissionKind::Drawing => {
+                Err("This round is for drawings".to_string())
+            }
+            Submission::Drawing(_) if *kind == SubmissionKind::Text => {
+                Err("This round is for text".to_string())
+            }
+            Submission::Text(text) => {
+                let text = text.trim();
+                let len = text.chars().count();
+
+                if len == 0 || len > MAX_TEXT_LEN {
+                    Err(format!("Text must be 1 to {} characters", MAX_TEXT_LEN))
+                } else if text.chars().any(char::is_control) {
+                    Err("Text can't have control characters".to_string())
+                } else {
+                    Ok(Submission::Text(text.to_string()))
+                }
+            }
+            Submission::Drawing(strokes) => {
+                let points: usize = strokes.iter().map(|s| s.points.len()).sum();
+
+                if strokes.is_empty() || points == 0 {
+                    return Err("The drawing is empty".to_string());
+                }
+                if strokes.len() > MAX_STROKES || points > MAX_DRAWING_POINTS {
+                    return Err(format!(
+                        "Drawings can have at most {} strokes and {} points",
+                        MAX_STROKES, MAX_DRAWING_POINTS
+                    ));
+                }
+
+                for stroke in strokes.iter() {
+                    let color = stroke.color.strip_prefix('#').unwrap_or("");
+                    if color.len() != 6 || !color.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(format!("{} isn't a colour like #1a2b3c", stroke.color));
+                    }
+                    if stroke.width == 0 || stroke.width > MAX_STROKE_WIDTH {
+                        return Err(format!("Strokes must be 1 to {} wide", MAX_STROKE_WIDTH));
+                    }
+                    if stroke
+                        .points
+                        .iter()
+                        .any(|(x, y)| *x > CANVAS_SIZE || *y > CANVAS_SIZE)
+                    {
+                        return Err(format!(
+                            "Points must be within the {0}x{0} canvas",
+                            CANVAS_SIZE
+                        ));
+                    }
+                }
+
+                Ok(Submission::Drawing(strokes))
+            }
+        }
+    }
+}
+
+/// A submission up for votes, without who made it
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Entry {
+    pub id: usize,
+    pub submission: Submission,
+}
+
+/// How a submission did, once its author is revealed
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EntryResult {
+    pub id: usize,
+    pub user_name: String,
+    pub submission: Submission,
+    pub votes: usize,
+    pub points: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum PromptAction {
+    /// A player's answer to the prompt. Submissions are final.
+    Submit { submission: Submission },
+    /// A vote for a submission, by its entry id. Votes can be changed until
+    /// voting closes, but never for your own submission.
+    Vote { entry: usize },
+    /// The host takes a submission down, and any votes it had with it
+    Remove { entry: usize },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum PromptEvent {
+    PromptStarted {
+        round: usize,
+        total_rounds: usize,
+        prompt: String,
+        submissions: SubmissionKind,
+        seconds_left: u64,
+        /// When submissions close, in milliseconds since the Unix epoch
+        deadline: Option<u64>,
+    },
+    /// A player has submitted. What they submitted stays hidden until voting.
+    SubmissionReceived { round: usize, user_name: String },
+    /// Everything submitted this round, shuffled and without authors
+    VotingOpen {
+        round: usize,
+        prompt: String,
+        entries: Vec<Entry>,
+        seconds_left: u64,
+        /// When voting closes, in milliseconds since the Unix epoch
+        deadline: Option<u64>,
+    },
+    /// The host took an entry down
+    EntryRemoved { round: usize, entry: usize },
+    /// The votes, with every entry's author, most votes first
+    RoundResults {
+        round: usize,
+        prompt: String,
+        results: Vec<EntryResult>,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum Phase {
+    /// Waiting on the game to start
+    Idle,
+    Submitting {
+        deadline: Instant,
+    },
+    Voting {
+        deadline: Instant,
+    },
+    /// Showing the results until the next prompt
+    ShowingResults {
+        until: Instant,
+    },
+}
+
+/// A submission for the round, kept with its author's user index
+#[derive(Debug, Clone)]
+struct Submitted {
+    author: usize,
+    submission: Submission,
+    removed: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PromptGame {
+    settings: PromptSettings,
+    /// The prompt for each round of this match
+    prompts: Vec<String>,
+    phase: Phase,
+    /// This round's submissions. Once voting opens they're shuffled, and an
+    /// entry's id is its place in here.
+    submitted: Vec<Submitted>,
+    /// Votes for this round's entries, by the voter's user index
+    votes: HashMap<usize, usize>,
+    /// How the last round went, for anyone joining while it's up
+    last_results: Option<PromptEvent>,
+    finished: bool,
+}
+
+impl Game for PromptGame {
+    fn start(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        let mut prompts: Vec<String> = if self.settings.prompts.is_empty() {
+            PROMPTS.iter().map(|p| p.to_string()).collect()
+        } else {
+            self.settings.prompts.clone()
+        };
+        prompts.shuffle(&mut thread_rng());
+
+        // Go round again if there are more rounds than prompts
+        self.prompts = prompts
+            .iter()
+            .cycle()
+            .take(room.total_rounds)
+            .cloned()
+            .collect();
+
+        room.broadcast(&room.game_start_update(room_code));
+        room.broadcast(&room.score_update(room_code));
+
+        self.open_submissions(room, room_code);
+    }
+
+    fn handle_action(
+        &mut self,
+        room: &mut GameLobbyState,
+        room_code: &str,
+        user_index: usize,
+        action: GameAction,
+    ) -> Result<(), GameLobbyError> {
+        let action = match action {
+            GameAction::Prompt(action) => action,
+            _ => return Err(GameLobbyError::wrong_game()),
+        };
+
+        match action {
+            PromptAction::Submit { submission } => {
+                self.submit(room, room_code, user_index, submission)
+            }
+            PromptAction::Vote { entry } => self.vote(room, room_code, user_index, entry),
+            PromptAction::Remove { entry } => self.remove(room, room_code, user_index, entry),
+        }
+    }
+
+    fn tick(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        let now = Instant::now();
+
+        match self.phase {
+            Phase::Submitting { deadline } if now >= deadline => self.open_voting(room, room_code),
+            Phase::Voting { deadline } if now >= deadline => self.show_results(room, room_code),
+            Phase::ShowingResults { until } if now >= until => {
+                room.current_round += 1;
+                room.broadcast(&room.score_update(room_code));
+                self.open_submissions(room, room_code);
+            }
+            _ => {}
+        }
+    }
+
+    /// Don't let the rest of the room wait on someone who's gone
+    fn player_left(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        match self.phase {
+            Phase::Submitting { .. } if self.everyone_submitted(room) => {
+                self.open_voting(room, room_code)
+            }
+            Phase::Voting { .. } if self.everyone_voted(room) => self.show_results(room, room_code),
+            _ => {}
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn snapshot(&self, room: &GameLobbyState) -> Vec<GameEvent> {
+        if self.finished {
+            return Vec::new();
+        }
+
+        match self.phase {
+            Phase::Submitting { deadline } => {
+                vec![self.prompt_started_update(room, deadline).into()]
+            }
+            Phase::Voting { deadline } => vec![self.voting_open_update(room, deadline).into()],
+            Phase::ShowingResults { .. } => self
+                .last_results
+                .clone()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            Phase::Idle => Vec::new(),
+        }
+    }
+}
+
+impl PromptGame {
+    pub fn new(settings: PromptSettings) -> Self {
+        PromptGame {
+            settings,
+            prompts: Vec::new(),
+            phase: Phase::Idle,
+            submitted: Vec::new(),
+            votes: HashMap::new(),
+            last_results: None,
+            finished: false,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        self.phase = Phase::Idle;
+    }
+
+    fn current_prompt(&self, room: &GameLobbyState) -> &str {
+        &self.prompts[room.current_round - 1]
+    }
+
+    fn open_submissions(&mut self, room: &GameLobbyState, room_code: &str) {
+        let limit = PromptSettings::phase_time(
+            self.settings.submit_secs,
+            room.round_time_limit.unwrap_or(DEFAULT_SUBMIT_TIME),
+        );
+        let deadline = Instant::now() + limit;
+
+        self.submitted.clear();
+        self.votes.clear();
+        self.last_results = None;
+        self.phase = Phase::Submitting { deadline };
+
+        info!(
+            "({}) Prompt {} of {}: {}",
+            room_code,
+            room.current_round,
+            room.total_rounds,
+            self.current_prompt(room)
+        );
+
+        room.broadcast_event(room_code, self.prompt_started_update(room, deadline));
+    }
+
+    fn prompt_started_update(&self, room: &GameLobbyState, deadline: Instant) -> PromptEvent {
+        let time_left = deadline.saturating_duration_since(Instant::now());
+
+        PromptEvent::PromptStarted {
+            round: room.current_round,
+            total_rounds: room.total_rounds,
+            prompt: self.current_prompt(room).to_string(),
+            submissions: self.settings.submissions.clone(),
+            seconds_left: time_left.as_secs_f64().ceil() as u64,
+            deadline: game::epoch_millis(time_left),
+        }
+    }
+
+    fn submit(
+        &mut self,
+        room: &mut GameLobbyState,
+        room_code: &str,
+        user_index: usize,
+        submission: Submission,
+    ) -> Result<(), GameLobbyError> {
+        let player = &room.users[user_index];
+        let user_name = player.user_name.clone();
+
+        if !player.user_type.is_player() {
+            return Err(GameLobbyError::new(
+                ErrorCode::NotAPlayer,
+                "The audience gets to vote, not submit".to_string(),
+            ));
+        }
+
+        // The round clock will open voting on its next tick
+        if !matches!(self.phase, Phase::Submitting { deadline } if Instant::now() < deadline) {
+            return Err(GameLobbyError::new(
+                ErrorCode::RoundTimedOut,
+                format!("Submissions for round {} are closed", room.current_round),
+            ));
+        }
+
+        if self.submitted.iter().any(|s| s.author == user_index) {
+            return Err(GameLobbyError::new(
+                ErrorCode::AlreadyPlayedRound,
+                format!(
+                    "{} already submitted for round {}",
+                    user_name, room.current_round
+                ),
+            ));
+        }
+
+        let submission = submission
+            .check(&self.settings.submissions)
+            .map_err(|e| GameLobbyError::new(ErrorCode::InvalidSubmission, e))?;
+
+        for moderator in self.settings.moderators.iter() {
+            if let Err(reason) = moderator.review(&submission) {
+                info!(
+                    "({}) Turned away {}'s submission: {}",
+                    room_code, user_name, reason
+                );
+                return Err(GameLobbyError::new(ErrorCode::SubmissionRejected, reason));
+            }
+        }
+
+        info!(
+            "({}) {} submitted for round {}",
+            room_code, user_name, room.current_round
+        );
+
+        self.submitted.push(Submitted {
+            author: user_index,
+            submission,
+            removed: false,
+        });
+        room.broadcast_event(
+            room_code,
+            PromptEvent::SubmissionReceived {
+                round: room.current_round,
+                user_name,
+            },
+        );
+
+        if self.everyone_submitted(room) {
+            self.open_voting(room, room_code);
+        }
+
+        Ok(())
+    }
+
+    fn everyone_submitted(&self, room: &GameLobbyState) -> bool {
+        room.users
+            .iter()
+            .enumerate()
+            .filter(|(_, u)| u.user_type.is_player() && u.connected)
+            .all(|(i, _)| self.submitted.iter().any(|s| s.author == i))
+    }
+
+    /// Shuffle the submissions so their order gives nothing away, and let
+    /// everyone see them. A round nobody submitted for goes straight to results.
+    fn open_voting(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        if self.submitted.is_empty() {
+            return self.show_results(room, room_code);
+        }
+
+        self.submitted.shuffle(&mut thread_rng());
+
+        let deadline =
+            Instant::now() + PromptSettings::phase_time(self.settings.vote_secs, DEFAULT_VOTE_TIME);
+        self.phase = Phase::Voting { deadline };
+
+        info!(
+            "({}) Voting on {} submissions for round {}",
+            room_code,
+            self.submitted.len(),
+            room.current_round
+        );
+
+        room.broadcast_event(room_code, self.voting_open_update(room, deadline));
+    }
+
+    fn voting_open_update(&self, room: &GameLobbyState, deadline: Instant) -> PromptEvent {
+        let time_left = deadline.saturating_duration_since(Instant::now());
+
+        PromptEvent::VotingOpen {
+            round: room.current_round,
+            prompt: self.current_prompt(room).to_string(),
+            entries: self
+                .submitted
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| !s.removed)
+                .map(|(id, s)| Entry {
+                    id,
+                    submission: s.submission.clone(),
+                })
+                .collect(),
+            seconds_left: time_left.as_secs_f64().ceil() as u64,
+            deadline: game::epoch_millis(time_left),
+        }
+    }
+
+    fn check_voting(&self, room: &GameLobbyState) -> Result<(), GameLobbyError> {
+        match self.phase {
+            Phase::Voting { deadline } if Instant::now() < deadline => Ok(()),
+            _ => Err(GameLobbyError::new(
+                ErrorCode::VotingClosed,
+                format!("Voting for round {} isn't open", room.current_round),
+            )),
+        }
+    }
+
+    fn vote(
+        &mut self,
+        room: &mut GameLobbyState,
+        room_code: &str,
+        user_index: usize,
+        entry: usize,
+    ) -> Result<(), GameLobbyError> {
+        self.check_voting(room)?;
+
+        // Entries are numbered from 1 wherever people see them
+        match self.submitted.get(entry) {
+            Some(s) if s.removed => {
+                return Err(GameLobbyError::new(
+                    ErrorCode::InvalidVote,
+                    format!("Entry {} was taken down", entry + 1),
+                ))
+            }
+            Some(s) if s.author == user_index => {
+                return Err(GameLobbyError::new(
+                    ErrorCode::InvalidVote,
+                    "You can't vote for your own submission".to_string(),
+                ))
+            }
+            Some(_) => {}
+            None => {
+                return Err(GameLobbyError::new(
+                    ErrorCode::InvalidVote,
+                    format!("There's no entry {}", entry.saturating_add(1)),
+                ))
+            }
+        }
+
+        self.votes.insert(user_index, entry);
+
+        if self.everyone_voted(room) {
+            self.show_results(room, room_code);
+        }
+
+        Ok(())
+    }
+
+    /// Every connected player with something to vote for has voted. The
+    /// audience's votes count, but voting doesn't wait on them.
+    fn everyone_voted(&self, room: &GameLobbyState) -> bool {
+        room.users
+            .iter()
+            .enumerate()
+            .filter(|(_, u)| u.user_type.is_player() && u.connected)
+            .all(|(i, _)| {
+                self.votes.contains_key(&i)
+                    || !self.submitted.iter().any(|s| !s.removed && s.author != i)
+            })
+    }
+
+    fn remove(
+        &mut self,
+        room: &mut GameLobbyState,
+        room_code: &str,
+        user_index: usize,
+        entry: usize,
+    ) -> Result<(), GameLobbyError> {
+        if !matches!(room.users[user_index].user_type, UserType::Host) {
+            return Err(GameLobbyError::new(
+                ErrorCode::NotHost,
+                "Only the host can take entries down".to_string(),
+            ));
+        }
+
+        self.check_voting(room)?;
+
+        let submitted = match self.submitted.get_mut(entry) {
+            Some(s) if !s.removed => s,
+            _ => {
+                return Err(GameLobbyError::new(
+                    ErrorCode::InvalidVote,
+                    format!("There's no entry {} to take down", entry.saturating_add(1)),
+                ))
+            }
+        };
+
+        submitted.removed = true;
+        self.votes.retain(|_, voted| *voted != entry);
+
+        info!(
+            "({}) Host took down {}'s submission",
+            room_code, room.users[submitted.author].user_name
+        );
+
+        room.broadcast_event(
+            room_code,
+            PromptEvent::EntryRemoved {
+                round: room.current_round,
+                entry,
+            },
+        );
+
+        if self.everyone_voted(room) {
+            self.show_results(room, room_code);
+        }
+
+        Ok(())
+    }
+
+    /// Count the votes, reveal who made what and score the round. The most
+    /// voted entries win the round, as long as they got any votes.
+    fn show_results(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        let current_round = room.current_round;
+
+        let mut results: Vec<EntryResult> = self
+            .submitted
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| !s.removed)
+            .map(|(id, s)| {
+                let votes = self.votes.values().filter(|voted| **voted == id).count();
+
+                EntryResult {
+                    id,
+                    user_name: room.users[s.author].user_name.clone(),
+                    submission: s.submission.clone(),
+                    votes,
+                    points: votes * POINTS_PER_VOTE,
+                }
+            })
+            .collect();
+        results.sort_by(|a, b| b.votes.cmp(&a.votes).then(a.id.cmp(&b.id)));
+
+        let most_votes = results.first().map_or(0, |r| r.votes);
+
+        for (i, player) in room.users.iter_mut().enumerate() {
+            let result = results.iter().find(|r| self.submitted[r.id].author == i);
+
+            // Players who left without submitting sit the round out
+            if !player.user_type.is_player() || (result.is_none() && !player.connected) {
+                continue;
+            }
+
+            match result {
+                Some(r) if most_votes > 0 && r.votes == most_votes => player.score.wins += 1,
+                _ => player.score.losses += 1,
+            }
+            player.score.points += result.map_or(0, |r| r.points);
+            player.rounds_played = current_round;
+        }
+
+        info!(
+            "({}) Round {} results: {:?}",
+            room_code,
+            current_round,
+            results
+                .iter()
+                .map(|r| (&r.user_name, r.votes))
+                .collect::<Vec<_>>()
+        );
+
+        let update = PromptEvent::RoundResults {
+            round: current_round,
+            prompt: self.current_prompt(room).to_string(),
+            results,
+        };
+        room.broadcast_event(room_code, update.clone());
+        self.last_results = Some(update);
+
+        if current_round < room.total_rounds {
+            room.broadcast(&room.score_update(room_code));
+            self.phase = Phase::ShowingResults {
+                until: Instant::now() + RESULTS_TIME,
+            };
+        } else {
+            self.finish();
+        }
+    }
+}
@@ -0,0 +1,438 @@
+//! Question packs for trivia, read from the server's question pack directory.
+//!
+//! A pack is a TOML or JSON file, and is picked by its file name without the
+//! extension, so `questions/science.toml` is the "science" pack. Packs are
+//! read when a host starts a game with them, so new ones can be dropped in
+//! without restarting the server.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+/// Fewest answers a question can offer
+pub const MIN_ANSWERS: usize = 2;
+/// Most answers a question can offer, so the buttons still fit
+pub const MAX_ANSWERS: usize = 6;
+/// Longest a question can be, in characters
+pub const MAX_QUESTION_LEN: usize = 300;
+/// Longest an answer can be, in characters
+pub const MAX_ANSWER_LEN: usize = 100;
+/// Longest a pack's name can be
+pub const MAX_PACK_NAME_LEN: usize = 64;
+
+/// File extensions packs can have, in the order they're looked for
+const EXTENSIONS: [&str; 2] = ["toml", "json"];
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Question {
+    pub question: String,
+    pub answers: Vec<String>,
+    /// The right answer, by index into `answers`
+    pub correct: usize,
+    /// Seconds players get for this question, instead of the pack's time
+    #[serde(default)]
+    pub time_limit_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct QuestionPack {
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Seconds players get for each question, instead of the room's round time
+    #[serde(default)]
+    pub time_limit_secs: Option<u64>,
+    pub questions: Vec<Question>,
+}
+
+/// What a host needs to know to pick a pack
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct QuestionPackSummary {
+    pub name: String,
+    pub title: Option<String>,
+    pub questions: usize,
+}
+
+#[derive(Debug)]
+pub enum QuestionPackError {
+    /// Pack names are file names, so they're kept to letters, numbers, '-' and '_'
+    BadName(String),
+    NotFound(String),
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, String),
+    NoQuestions,
+    NoTime,
+    /// Something is wrong with a question, numbered from 1
+    BadQuestion(usize, String),
+}
+
+impl fmt::Display for QuestionPackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QuestionPackError::BadName(name) => write!(
+                f,
+                "Question pack name \"{}\" must be 1 to {} letters, numbers, '-' or '_'",
+                name, MAX_PACK_NAME_LEN
+            ),
+            QuestionPackError::NotFound(name) => write!(f, "There's no question pack {}", name),
+            QuestionPackError::Read(path, e) => {
+                write!(f, "Unable to read {}: {}", path.display(), e)
+            }
+            QuestionPackError::Parse(path, e) => {
+                write!(f, "Unable to parse {}: {}", path.display(), e)
+            }
+            QuestionPackError::NoQuestions => write!(f, "A question pack needs some questions"),
+            QuestionPackError::NoTime => write!(f, "time_limit_secs must be at least 1"),
+            QuestionPackError::BadQuestion(number, reason) => {
+                write!(f, "Question {}: {}", number, reason)
+            }
+        }
+    }
+}
+
+impl QuestionPack {
+    /// Read and check the pack called `name` in `dir`
+    pub async fn load(dir: &Path, name: &str) -> Result<Self, QuestionPackError> {
+        let path = QuestionPack::find(dir, name).await?;
+
+        let contents = fs::read_to_string(&path)
+            .await
+            .map_err(|e| QuestionPackError::Read(path.clone(), e))?;
+
+        QuestionPack::parse(&path, &contents)
+    }
+
+    /// Where the pack called `name` is, checking the name first so it can't
+    /// reach outside `dir`
+    async fn find(dir: &Path, name: &str) -> Result<PathBuf, QuestionPackError> {
+        if name.is_empty()
+            || name.len() > MAX_PACK_NAME_LEN
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(QuestionPackError::BadName(name.to_string()));
+        }
+
+        for ext in EXTENSIONS.iter() {
+            let path = dir.join(format!("{}.{}", name, ext));
+            if fs::metadata(&path).await.is_ok_and(|m| m.is_file()) {
+                return Ok(path);
+            }
+        }
+
+        Err(QuestionPackError::NotFound(name.to_string()))
+    }
+
+    /// Check a pack read from `path`, which is JSON or TOML going by its extension
+    fn parse(path: &Path, contents: &str) -> Result<Self, QuestionPackError> {
+        let pack: QuestionPack = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(contents)
+                .map_err(|e| QuestionPackError::Parse(path.into(), e.to_string()))?,
+            _ => toml::from_str(contents)
+                .map_err(|e| QuestionPackError::Parse(path.into(), e.to_string()))?,
+        };
+
+        pack.validate()?;
+        Ok(pack)
+    }
+
+    fn validate(&self) -> Result<(), QuestionPackError> {
+        if self.questions.is_empty() {
+            return Err(QuestionPackError::NoQuestions);
+        }
+
+        if self.time_limit_secs == Some(0) {
+            return Err(QuestionPackError::NoTime);
+        }
+
+        for (i, q) in self.questions.iter().enumerate() {
+            let bad = |reason: String| Err(QuestionPackError::BadQuestion(i + 1, reason));
+
+            let question_len = q.question.trim().chars().count();
+            if question_len == 0 || question_len > MAX_QUESTION_LEN {
+                return bad(format!(
+                    "The question must be 1 to {} characters",
+                    MAX_QUESTION_LEN
+                ));
+            }
+
+            if q.answers.len() < MIN_ANSWERS || q.answers.len() > MAX_ANSWERS {
+                return bad(format!(
+                    "There must be {} to {} answers",
+                    MIN_ANSWERS, MAX_ANSWERS
+                ));
+            }
+
+            for answer in q.answers.iter() {
+                let len = answer.trim().chars().count();
+                if len == 0 || len > MAX_ANSWER_LEN {
+                    return bad(format!(
+                        "Answers must be 1 to {} characters",
+                        MAX_ANSWER_LEN
+                    ));
+                }
+            }
+
+            if q.correct >= q.answers.len() {
+                return bad(format!(
+                    "correct is {}, but answers are numbered 0 to {}",
+                    q.correct,
+                    q.answers.len() - 1
+                ));
+            }
+
+            if q.time_limit_secs == Some(0) {
+                return bad(QuestionPackError::NoTime.to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every pack in `dir` that can be played, by name. Packs that don't load
+    /// are left out, and logged so whoever wrote them can find out why.
+    pub async fn list(dir: &Path) -> Vec<QuestionPackSummary> {
+        let mut entries = match fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Unable to read question packs in {}: {}", dir.display(), e);
+                return Vec::new();
+            }
+        };
+
+        let mut packs = Vec::new();
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+
+            if !path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| EXTENSIONS.contains(&ext))
+            {
+                continue;
+            }
+
+            let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+
+            match QuestionPack::load(dir, &name).await {
+                Ok(pack) => packs.push(QuestionPackSummary {
+                    name,
+                    title: pack.title,
+                    questions: pack.questions.len(),
+                }),
+                Err(e) => eprintln!("Skipping question pack {}: {}", path.display(), e),
+            }
+        }
+
+        // A pack in both formats is only played from one of them
+        packs.sort_by(|a, b| a.name.cmp(&b.name));
+        packs.dedup_by(|a, b| a.name == b.name);
+        packs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question(answers: usize, correct: usize) -> Question {
+        Question {
+            question: "Which one?".to_string(),
+            answers: (0..answers).map(|i| format!("Answer {}", i)).collect(),
+            correct,
+            time_limit_secs: None,
+        }
+    }
+
+    fn pack(questions: Vec<Question>) -> QuestionPack {
+        QuestionPack {
+            questions,
+            ..QuestionPack::default()
+        }
+    }
+
+    /// A directory of its own for a test to put packs in
+    fn pack_dir(test: &str, packs: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("question-packs-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for (file_name, contents) in packs {
+            std::fs::write(dir.join(file_name), contents).unwrap();
+        }
+        dir
+    }
+
+    const TOML_PACK: &str = r#"
+title = "Colours"
+
+[[questions]]
+question = "What colour is the sky?"
+answers = ["Blue", "Green"]
+correct = 0
+"#;
+
+    const JSON_PACK: &str = r#"{
+        "time_limit_secs": 10,
+        "questions": [
+            { "question": "What is 2 + 2?", "answers": ["3", "4", "5"], "correct": 1 }
+        ]
+    }"#;
+
+    #[test]
+    fn parses_by_extension() {
+        let toml = QuestionPack::parse(Path::new("colours.toml"), TOML_PACK).unwrap();
+        assert_eq!(toml.title.as_deref(), Some("Colours"));
+        assert_eq!(toml.questions[0].answers, vec!["Blue", "Green"]);
+
+        let json = QuestionPack::parse(Path::new("maths.json"), JSON_PACK).unwrap();
+        assert_eq!(json.time_limit_secs, Some(10));
+        assert_eq!(json.questions[0].correct, 1);
+
+        assert!(matches!(
+            QuestionPack::parse(Path::new("maths.toml"), JSON_PACK),
+            Err(QuestionPackError::Parse(..))
+        ));
+        assert!(matches!(
+            QuestionPack::parse(Path::new("colours.json"), TOML_PACK),
+            Err(QuestionPackError::Parse(..))
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let contents = TOML_PACK.replace("correct = 0", "correct = 0\nhint = \"Look up\"");
+
+        assert!(matches!(
+            QuestionPack::parse(Path::new("colours.toml"), &contents),
+            Err(QuestionPackError::Parse(..))
+        ));
+    }
+
+    #[test]
+    fn needs_questions() {
+        assert!(matches!(
+            pack(Vec::new()).validate(),
+            Err(QuestionPackError::NoQuestions)
+        ));
+    }
+
+    #[test]
+    fn keeps_answer_counts_in_bounds() {
+        assert!(pack(vec![question(MIN_ANSWERS, 0)]).validate().is_ok());
+        assert!(pack(vec![question(MAX_ANSWERS, 0)]).validate().is_ok());
+
+        // Questions are numbered from 1, so whoever wrote the pack can find them
+        assert!(matches!(
+            pack(vec![question(2, 0), question(MIN_ANSWERS - 1, 0)]).validate(),
+            Err(QuestionPackError::BadQuestion(2, _))
+        ));
+        assert!(matches!(
+            pack(vec![question(MAX_ANSWERS + 1, 0)]).validate(),
+            Err(QuestionPackError::BadQuestion(1, _))
+        ));
+    }
+
+    #[test]
+    fn needs_the_correct_answer_to_be_one_of_the_answers() {
+        assert!(pack(vec![question(3, 2)]).validate().is_ok());
+        assert!(matches!(
+            pack(vec![question(3, 3)]).validate(),
+            Err(QuestionPackError::BadQuestion(1, _))
+        ));
+    }
+
+    #[test]
+    fn rejects_blank_and_long_text() {
+        let mut blank = question(2, 0);
+        blank.question = "   ".to_string();
+        assert!(pack(vec![blank]).validate().is_err());
+
+        let mut long_answer = question(2, 0);
+        long_answer.answers[1] = "a".repeat(MAX_ANSWER_LEN + 1);
+        assert!(pack(vec![long_answer]).validate().is_err());
+    }
+
+    #[test]
+    fn rejects_zero_time_limits() {
+        let mut no_time = pack(vec![question(2, 0)]);
+        no_time.time_limit_secs = Some(0);
+        assert!(matches!(no_time.validate(), Err(QuestionPackError::NoTime)));
+
+        let mut quick = question(2, 0);
+        quick.time_limit_secs = Some(0);
+        assert!(matches!(
+            pack(vec![question(2, 0), quick]).validate(),
+            Err(QuestionPackError::BadQuestion(2, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn keeps_names_inside_the_pack_directory() {
+        let dir = pack_dir("names", &[("colours.toml", TOML_PACK)]);
+        std::fs::write(dir.with_extension("toml"), TOML_PACK).unwrap();
+
+        let outside = format!("../{}", dir.file_name().unwrap().to_str().unwrap());
+        let too_long = "a".repeat(MAX_PACK_NAME_LEN + 1);
+
+        for name in [
+            outside.as_str(),
+            "",
+            "colours.toml",
+            "a/b",
+            "/etc/passwd",
+            &too_long,
+        ] {
+            assert!(
+                matches!(
+                    QuestionPack::load(&dir, name).await,
+                    Err(QuestionPackError::BadName(_))
+                ),
+                "{:?} was let through",
+                name
+            );
+        }
+
+        assert!(matches!(
+            QuestionPack::load(&dir, "missing").await,
+            Err(QuestionPackError::NotFound(_))
+        ));
+        assert!(QuestionPack::load(&dir, "colours").await.is_ok());
+
+        std::fs::remove_file(dir.with_extension("toml")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn lists_the_packs_that_load() {
+        let dir = pack_dir(
+            "list",
+            &[
+                ("maths.json", JSON_PACK),
+                ("colours.toml", TOML_PACK),
+                ("colours.json", JSON_PACK),
+                ("broken.toml", "questions = []"),
+                ("notes.txt", TOML_PACK),
+            ],
+        );
+
+        let packs = QuestionPack::list(&dir).await;
+        let names: Vec<&str> = packs.iter().map(|p| p.name.as_str()).collect();
+
+        assert_eq!(names, vec!["colours", "maths"]);
+        assert_eq!(packs[0].title.as_deref(), Some("Colours"));
+        assert_eq!(packs[1].questions, 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+//! Room codes, the short codes players type in to join a room.
+//!
+//! Codes are picked at random from the server's alphabet, skipping any in use
+//! and any that spell a blocked word, since they end up on stream.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use rand::{seq::SliceRandom, thread_rng, Rng};
+
+/// Leaves out I and O so codes can't be confused with 1 and 0 on stream
+pub const DEFAULT_ALPHABET: &str = "ABCDEFGHJKLMNPQRSTUVWXYZ";
+pub const DEFAULT_LENGTH: usize = 4;
+
+/// Words we never want to show up inside a room code
+pub const DEFAULT_BLOCKLIST: &[&str] = &[
+    "ANAL", "ANUS", "ARSE", "CLIT", "COCK", "COON", "CUNT", "DICK", "DYKE", "FAG", "FUCK", "JIZZ",
+    "KIKE", "NAZI", "PAKI", "PISS", "POOP", "PORN", "RAPE", "SHIT", "SLUT", "SPIC", "TWAT", "WANK",
+    "WHORE",
+];
+
+/// Random picks to try before falling back to walking the code space
+const RANDOM_ATTEMPTS: usize = 32;
+/// Most codes we'll walk through looking for a free one
+const SCAN_LIMIT: u128 = 1_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoomCodeError {
+    /// Every code is either in use or blocked
+    Exhausted,
+    EmptyAlphabet,
+    ZeroLength,
+}
+
+impl fmt::Display for RoomCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RoomCodeError::Exhausted => write!(f, "No room codes are available"),
+            RoomCodeError::EmptyAlphabet => write!(f, "Room code alphabet can't be empty"),
+            RoomCodeError::ZeroLength => write!(f, "Room codes need at least one character"),
+        }
+    }
+}
+
+/// Hands out room codes that aren't already in use and don't spell anything
+/// from the blocklist
+#[derive(Debug, Clone)]
+pub struct RoomCodeAllocator {
+    length: usize,
+    alphabet: Vec<char>,
+    blocklist: Vec<String>,
+}
+
+impl Default for RoomCodeAllocator {
+    fn default() -> Self {
+        RoomCodeAllocator::new(
+            DEFAULT_LENGTH,
+            DEFAULT_ALPHABET,
+            DEFAULT_BLOCKLIST.iter().map(|w| w.to_string()).collect(),
+        )
+        .expect("Default room code settings are valid")
+    }
+}
+
+impl RoomCodeAllocator {
+    /// Players type room codes in any case, so the alphabet and blocklist are
+    /// uppercased to match
+    pub fn new(
+        length: usize,
+        alphabet: &str,
+        blocklist: Vec<String>,
+    ) -> Result<Self, RoomCodeError> {
+        if length == 0 {
+            return Err(RoomCodeError::ZeroLength);
+        }
+
+        let mut seen = HashSet::new();
+        let alphabet: Vec<char> = alphabet
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.to_ascii_uppercase())
+            .filter(|c| seen.insert(*c))
+            .collect();
+
+        if alphabet.is_empty() {
+            return Err(RoomCodeError::EmptyAlphabet);
+        }
+
+        let blocklist = blocklist
+            .iter()
+            .map(|w| w.trim().to_uppercase())
+            .filter(|w| !w.is_empty())
+            .collect();
+
+        Ok(RoomCodeAllocator {
+            length,
+            alphabet,
+            blocklist,
+        })
+    }
+
+    /// Pick a code that isn't a key in `taken`
+    pub fn allocate<V>(&self, taken: &HashMap<String, V>) -> Result<String, RoomCodeError> {
+        let capacity = self.capacity();
+
+        if let Some(capacity) = capacity {
+            if taken.len() as u128 >= capacity {
+                return Err(RoomCodeError::Exhausted);
+            }
+        }
+
+        let mut rng = thread_rng();
+        let usable = |code: &String| !taken.contains_key(code) && !self.is_blocked(code);
+
+        for _ in 0..RANDOM_ATTEMPTS {
+            let code: String = (0..self.length)
+                .map(|_| {
+                    *self
+                        .alphabet
+                        .choose(&mut rng)
+                        .expect("alphabet is not empty")
+                })
+                .collect();
+
+            if usable(&code) {
+                return Ok(code);
+            }
+        }
+
+        // The space is getting crowded. Walk it from a random spot so we
+        // either find a free code or know there isn't one.
+        let space = capacity.unwrap_or(u128::MAX);
+        let start = rng.gen_range(0, space);
+
+        (0..space.min(SCAN_LIMIT))
+            .map(|offset| self.code_at(start.wrapping_add(offset) % space))
+            .find(usable)
+            .ok_or(RoomCodeError::Exhausted)
+    }
+
+    fn is_blocked(&self, code: &str) -> bool {
+        self.blocklist
+            .iter()
+            .any(|word| code.contains(word.as_str()))
+    }
+
+    /// Number of possible codes, if it fits in a u128
+    fn capacity(&self) -> Option<u128> {
+        (self.alphabet.len() as u128).checked_pow(self.length as u32)
+    }
+
+    /// The `index`th code, counting through the alphabet like digits
+    fn code_at(&self, mut index: u128) -> String {
+        let base = self.alphabet.len() as u128;
+        let mut code = vec![self.alphabet[0]; self.length];
+
+        for c in code.iter_mut().rev() {
+            *c = self.alphabet[(index % base) as usize];
+            index /= base;
+        }
+
+        code.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocator(length: usize, alphabet: &str, blocklist: &[&str]) -> RoomCodeAllocator {
+        RoomCodeAllocator::new(
+            length,
+            alphabet,
+            blocklist.iter().map(|w| w.to_string()).collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn allocates_codes_from_the_alphabet() {
+        let codes = allocator(4, "abc", &[]);
+        let code = codes.allocate::<()>(&HashMap::new()).unwrap();
+
+        assert_eq!(code.len(), 4);
+        assert!(code.chars().all(|c| "ABC".contains(c)));
+    }
+
+    #[test]
+    fn skips_codes_in_use() {
+        let codes = allocator(1, "ABC", &[]);
+        let taken: HashMap<String, ()> = vec![("A".to_string(), ()), ("C".to_string(), ())]
+            .into_iter()
+            .collect();
+
+        for _ in 0..20 {
+            assert_eq!(codes.allocate(&taken).unwrap(), "B");
+        }
+    }
+
+    #[test]
+    fn skips_blocked_words_anywhere_in_the_code() {
+        let codes = allocator(3, "AB", &["ab"]);
+
+        for _ in 0..50 {
+            let code = codes.allocate::<()>(&HashMap::new()).unwrap();
+            assert!(!code.contains("AB"), "{} contains a blocked word", code);
+        }
+    }
+
+    #[test]
+    fn runs_out_when_every_code_is_taken() {
+        let codes = allocator(2, "AB", &[]);
+        let mut taken = HashMap::new();
+
+        for _ in 0..4 {
+            let code = codes.allocate(&taken).unwrap();
+            taken.insert(code, ());
+        }
+
+        assert_eq!(codes.allocate(&taken), Err(RoomCodeError::Exhausted));
+    }
+
+    #[test]
+    fn runs_out_when_every_code_is_blocked() {
+        let codes = allocator(2, "AB", &["A", "BB"]);
+
+        assert_eq!(
+            codes.allocate::<()>(&HashMap::new()),
+            Err(RoomCodeError::Exhausted)
+        );
+    }
+
+    #[test]
+    fn rejects_unusable_settings() {
+        assert_eq!(
+            RoomCodeAllocator::new(0, "ABC", Vec::new()).unwrap_err(),
+            RoomCodeError::ZeroLength
+        );
+        assert_eq!(
+            RoomCodeAllocator::new(4, " ", Vec::new()).unwrap_err(),
+            RoomCodeError::EmptyAlphabet
+        );
+    }
+}
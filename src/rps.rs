@@ -0,0 +1,986 @@
+//! Rock-paper-scissors, or whichever rule-set the room plays with.
+//!
+//! Players throw against the server, against each other or through a bracket,
+//! while the audience either predicts a player's hand or picks the server's.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+use log::info;
+use rand::{seq::SliceRandom, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::bracket::{Bracket, Elimination};
+use crate::game::{self, Game, GameAction, GameEvent};
+use crate::rule_set::{Hand, RoundOutcome, RuleSet};
+use crate::strategy::ServerStrategy;
+use crate::{ErrorCode, GameLobbyError, GameLobbyState, PlayerStanding, UserServerSideState};
+
+/// What the audience votes on during each round
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub enum AudienceMode {
+    /// Guess the hand the player in the spotlight will throw
+    #[default]
+    Predict,
+    /// Pick the server's hand. The most popular vote is thrown at the players.
+    CrowdVsPlayers,
+}
+
+/// Who players throw against
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub enum GameMode {
+    /// Every player plays the server
+    #[default]
+    VsServer,
+    /// Players are paired up each round, with anyone left over playing the server
+    PvP,
+    /// Players are knocked out after losing a match
+    SingleElimination,
+    /// Players are knocked out after losing two matches
+    DoubleElimination,
+}
+
+impl GameMode {
+    fn elimination(&self) -> Option<Elimination> {
+        match self {
+            GameMode::SingleElimination => Some(Elimination::Single),
+            GameMode::DoubleElimination => Some(Elimination::Double),
+            GameMode::VsServer | GameMode::PvP => None,
+        }
+    }
+}
+
+/// What happens to a player who doesn't throw before the round runs out of time
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub enum MissedThrow {
+    /// The player loses the round
+    #[default]
+    Forfeit,
+    /// The server throws a random hand for them
+    RandomThrow,
+}
+
+/// How the host set up the match
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RpsSettings {
+    pub audience_mode: AudienceMode,
+    pub missed_throw: MissedThrow,
+    pub game_mode: GameMode,
+    /// How the server picks its hand, when the audience isn't picking it
+    pub server_strategy: ServerStrategy,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Matchup {
+    user_name: String,
+    /// None when they're playing the server
+    opponent: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct VoteShare {
+    hand: Hand,
+    votes: usize,
+    percent: f32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum RpsAction {
+    /// A player's hand for the current round
+    Throw { hand: Hand },
+    /// An audience member's vote for the current round. Votes can be changed
+    /// until voting closes.
+    Vote { hand: Hand },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum RpsEvent {
+    RoundStarted {
+        round: usize,
+        total_rounds: usize,
+        /// None when the round waits for everyone
+        seconds_left: Option<u64>,
+        /// When the round runs out of time, in milliseconds since the Unix epoch
+        deadline: Option<u64>,
+        /// Who everyone is playing this round, when it isn't just the server
+        matchups: Vec<Matchup>,
+    },
+    /// A PvP player has thrown. Their hand stays hidden until their opponent throws.
+    HandLocked { round: usize, user_name: String },
+    /// A player ran out of time to throw. What happens to their turn depends
+    /// on the room's `MissedThrow` setting.
+    ThrowMissed { round: usize, user_name: String },
+    RoundResult {
+        user_name: String,
+        player_hand: Hand,
+        /// The hand played against them, by the server or their opponent
+        server_hand: Hand,
+        outcome: RoundOutcome,
+        /// Who they played, if it wasn't the server
+        opponent: Option<String>,
+    },
+    /// Sent whenever the bracket changes, in bracket games
+    BracketUpdate {
+        champion: Option<String>,
+        bracket: Bracket,
+    },
+    AudienceVoteOpen {
+        round: usize,
+        mode: AudienceMode,
+        /// The player the audience is guessing about, when predicting
+        spotlight: Option<String>,
+    },
+    AudienceVoteResult {
+        round: usize,
+        mode: AudienceMode,
+        /// The player whose throw the votes were counted for
+        user_name: String,
+        /// What was actually thrown: the player's hand when predicting, or
+        /// the hand the crowd played against them
+        hand: Hand,
+        votes: Vec<VoteShare>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct RpsGame {
+    settings: RpsSettings,
+    rule_set: RuleSet,
+    /// The player the audience is predicting for this round
+    spotlight: Option<String>,
+    /// Audience votes for this round, by index into the room's users, which
+    /// stays the same when someone resumes their session
+    votes: HashMap<usize, Hand>,
+    /// The hand the crowd plays against everyone this round, settled when it's
+    /// first thrown
+    crowd_pick: Option<Hand>,
+    /// When the current round runs out of time
+    round_deadline: Option<Instant>,
+    /// Each paired player's opponent for the current round, by user name
+    opponents: HashMap<String, String>,
+    /// Hands thrown in PvP that are waiting on the opponent's, by user name
+    hidden_hands: HashMap<String, Hand>,
+    /// Set for bracket games
+    bracket: Option<Bracket>,
+    finished: bool,
+}
+
+impl Game for RpsGame {
+    fn start(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        // Brackets run until someone wins, however many rounds that takes
+        self.bracket = self.settings.game_mode.elimination().map(|elimination| {
+            room.total_rounds = 0;
+
+            let entrants = room
+                .players()
+                .filter(|u| u.connected)
+                .map(|u| u.user_name.clone())
+                .collect();
+            Bracket::new(elimination, entrants)
+        });
+
+        room.broadcast(&room.game_start_update(room_code));
+        if let Some(update) = self.bracket_update() {
+            room.broadcast_event(room_code, update);
+        }
+        room.broadcast(&room.score_update(room_code));
+
+        if self.bracket.as_ref().is_some_and(|b| b.finished()) {
+            self.finish();
+        } else {
+            self.open_round(room, room_code);
+        }
+    }
+
+    fn handle_action(
+        &mut self,
+        room: &mut GameLobbyState,
+        room_code: &str,
+        user_index: usize,
+        action: GameAction,
+    ) -> Result<(), GameLobbyError> {
+        let action = match action {
+            GameAction::Rps(action) => action,
+            _ => return Err(GameLobbyError::wrong_game()),
+        };
+
+        match action {
+            RpsAction::Throw { hand } => self.throw(room, room_code, user_index, hand),
+            RpsAction::Vote { hand } => self.vote(room, user_index, hand),
+        }
+    }
+
+    fn tick(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        if self.round_timed_out() {
+            self.expire_round(room, room_code);
+        }
+    }
+
+    /// Don't let the rest of the room wait on a throw that will never come
+    fn player_left(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        self.advance_round(room, room_code);
+    }
+
+    /// Rounds missed while away are forfeited, so the player throws once for
+    /// the round being played instead of catching up on all of them
+    fn player_returned(&mut self, room: &mut GameLobbyState, room_code: &str, user_index: usize) {
+        // Bracket matches keep track of who still owes a throw themselves
+        if self.bracket.is_some() {
+            return;
+        }
+
+        let current_round = room.current_round;
+        let player = &mut room.users[user_index];
+        let missed = current_round
+            .saturating_sub(1)
+            .saturating_sub(player.rounds_played);
+
+        if missed == 0 {
+            return;
+        }
+
+        info!(
+            "({}) {} forfeits the {} round(s) they missed",
+            room_code, player.user_name, missed
+        );
+
+        for _ in 0..missed {
+            player.score.record(&RoundOutcome::Lose);
+        }
+        player.rounds_played += missed;
+
+        room.broadcast(&room.score_update(room_code));
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn snapshot(&self, room: &GameLobbyState) -> Vec<GameEvent> {
+        let mut events: Vec<GameEvent> = self.bracket_update().into_iter().collect();
+
+        if !self.finished {
+            events.push(self.round_started_update(room).into());
+            events.push(self.vote_open_update(room).into());
+        }
+
+        events
+    }
+
+    /// Bracket games go by how far players got in the bracket
+    fn standings(&self, room: &GameLobbyState) -> Vec<PlayerStanding> {
+        let bracket = match self.bracket.as_ref() {
+            Some(bracket) => bracket,
+            None => return room.standings(),
+        };
+
+        bracket
+            .placements()
+            .into_iter()
+            .filter_map(|(rank, user_name)| {
+                room.player_index(user_name).map(|i| PlayerStanding {
+                    rank,
+                    user_name: user_name.to_string(),
+                    score: room.users[i].score.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl RpsGame {
+    pub fn new(settings: RpsSettings, rule_set: RuleSet) -> Self {
+        RpsGame {
+            settings,
+            rule_set,
+            spotlight: None,
+            votes: HashMap::new(),
+            crowd_pick: None,
+            round_deadline: None,
+            opponents: HashMap::new(),
+            hidden_hands: HashMap::new(),
+            bracket: None,
+            finished: false,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        self.round_deadline = None;
+    }
+
+    fn throw(
+        &mut self,
+        room: &mut GameLobbyState,
+        room_code: &str,
+        user_index: usize,
+        hand: Hand,
+    ) -> Result<(), GameLobbyError> {
+        let current_round = room.current_round;
+        let player = &room.users[user_index];
+        let user_name = player.user_name.clone();
+
+        if !player.user_type.is_player() {
+            return Err(GameLobbyError::new(
+                ErrorCode::NotAPlayer,
+                "The audience can't throw hands".to_string(),
+            ));
+        }
+
+        if player.rounds_played >= current_round || self.hidden_hands.contains_key(&user_name) {
+            return Err(GameLobbyError::new(
+                ErrorCode::AlreadyPlayedRound,
+                format!(
+                    "{} already threw a hand for round {}",
+                    user_name, current_round
+                ),
+            ));
+        }
+
+        if self.bracket.is_some() && !self.opponents.contains_key(&user_name) {
+            return Err(GameLobbyError::new(
+                ErrorCode::NoMatch,
+                format!("{} has no match to play right now", user_name),
+            ));
+        }
+
+        // The round clock will settle this turn on its next tick
+        if self.round_timed_out() {
+            return Err(GameLobbyError::new(
+                ErrorCode::RoundTimedOut,
+                format!("Time ran out for round {}", current_round),
+            ));
+        }
+
+        let hand = self.check_hand(&hand)?;
+
+        info!("({}) {} played hand: {}", room_code, user_name, hand);
+
+        self.submit_hand(room, room_code, user_index, hand);
+        self.advance_round(room, room_code);
+
+        Ok(())
+    }
+
+    fn vote(
+        &mut self,
+        room: &GameLobbyState,
+        user_index: usize,
+        hand: Hand,
+    ) -> Result<(), GameLobbyError> {
+        let user = &room.users[user_index];
+
+        if user.user_type.is_player() {
+            return Err(GameLobbyError::new(
+                ErrorCode::NotAudience,
+                "Only the audience can vote".to_string(),
+            ));
+        }
+
+        // Predictions close once the player in the spotlight has thrown, and
+        // the crowd's hand once it's been played against anyone
+        let voting_closed = match self.settings.audience_mode {
+            AudienceMode::Predict => match &self.spotlight {
+                Some(spotlight) => room
+                    .players()
+                    .any(|u| &u.user_name == spotlight && u.rounds_played >= room.current_round),
+                None => true,
+            },
+            AudienceMode::CrowdVsPlayers => self.crowd_pick.is_some(),
+        };
+
+        if voting_closed {
+            return Err(GameLobbyError::new(
+                ErrorCode::VotingClosed,
+                format!("Voting for round {} is over", room.current_round),
+            ));
+        }
+
+        let hand = self.check_hand(&hand)?;
+        self.votes.insert(user_index, hand);
+
+        Ok(())
+    }
+
+    /// The room's own spelling of `hand`, as long as it's one of the room's moves
+    fn check_hand(&self, hand: &Hand) -> Result<Hand, GameLobbyError> {
+        self.rule_set.hand(hand.as_str()).ok_or_else(|| {
+            GameLobbyError::new(
+                ErrorCode::InvalidHand,
+                format!(
+                    "{} isn't a move here. Pick one of: {}",
+                    hand,
+                    self.rule_set.moves.join(", ")
+                ),
+            )
+        })
+    }
+
+    fn bracket_update(&self) -> Option<GameEvent> {
+        self.bracket.as_ref().map(|bracket| {
+            RpsEvent::BracketUpdate {
+                champion: bracket.champion().map(|c| c.to_string()),
+                bracket: bracket.clone(),
+            }
+            .into()
+        })
+    }
+
+    /// Start the clock on the current round, and get the audience ready to
+    /// vote on it
+    fn open_round(&mut self, room: &GameLobbyState, room_code: &str) {
+        self.round_deadline = room.round_time_limit.map(|limit| Instant::now() + limit);
+        self.pair_players(room);
+        room.broadcast_event(room_code, self.round_started_update(room));
+
+        self.votes.clear();
+        self.crowd_pick = None;
+
+        self.spotlight = match self.settings.audience_mode {
+            AudienceMode::Predict => {
+                // In a bracket, only players with a match to play are worth guessing about
+                let players: Vec<&UserServerSideState> = room
+                    .players()
+                    .filter(|u| {
+                        u.connected
+                            && (self.bracket.is_none() || self.opponents.contains_key(&u.user_name))
+                    })
+                    .collect();
+
+                if players.is_empty() {
+                    None
+                } else {
+                    let spotlight = players[(room.current_round - 1) % players.len()];
+                    Some(spotlight.user_name.clone())
+                }
+            }
+            AudienceMode::CrowdVsPlayers => None,
+        };
+
+        room.broadcast_event(room_code, self.vote_open_update(room));
+    }
+
+    /// Shuffle connected players into pairs for a PvP round. With an odd
+    /// number of players, whoever is left over plays the server.
+    fn pair_players(&mut self, room: &GameLobbyState) {
+        self.opponents.clear();
+        self.hidden_hands.clear();
+
+        // Bracket matches that still need a winner are played out
+        if let Some(bracket) = self.bracket.as_ref() {
+            for m in bracket.open_matches() {
+                let [first, second] = &m.players;
+                self.opponents.insert(first.clone(), second.clone());
+                self.opponents.insert(second.clone(), first.clone());
+            }
+            return;
+        }
+
+        if self.settings.game_mode != GameMode::PvP {
+            return;
+        }
+
+        let mut players: Vec<String> = room
+            .players()
+            .filter(|u| u.connected)
+            .map(|u| u.user_name.clone())
+            .collect();
+        players.shuffle(&mut thread_rng());
+
+        for pair in players.chunks_exact(2) {
+            self.opponents.insert(pair[0].clone(), pair[1].clone());
+            self.opponents.insert(pair[1].clone(), pair[0].clone());
+        }
+    }
+
+    fn matchups(&self, room: &GameLobbyState) -> Vec<Matchup> {
+        if self.settings.game_mode == GameMode::VsServer {
+            return Vec::new();
+        }
+
+        // Players in a bracket without a match this round aren't playing anyone
+        room.players()
+            .filter(|u| self.bracket.is_none() || self.opponents.contains_key(&u.user_name))
+            .map(|u| Matchup {
+                user_name: u.user_name.clone(),
+                opponent: self.opponents.get(&u.user_name).cloned(),
+            })
+            .collect()
+    }
+
+    fn round_started_update(&self, room: &GameLobbyState) -> RpsEvent {
+        let time_left = self
+            .round_deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+
+        RpsEvent::RoundStarted {
+            round: room.current_round,
+            total_rounds: room.total_rounds,
+            seconds_left: time_left.map(|left| left.as_secs_f64().ceil() as u64),
+            deadline: time_left.and_then(game::epoch_millis),
+            matchups: self.matchups(room),
+        }
+    }
+
+    fn round_timed_out(&self) -> bool {
+        self.round_deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Settle the turns of everyone who didn't throw in time, then move on
+    fn expire_round(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        let current_round = room.current_round;
+        let missed: Vec<usize> = room
+            .users
+            .iter()
+            .enumerate()
+            .filter(|(_, u)| u.user_type.is_player() && u.connected && self.awaiting_throw(room, u))
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut forfeited = HashSet::new();
+
+        for i in missed {
+            let user_name = room.users[i].user_name.clone();
+            info!(
+                "({}) {} ran out of time in round {}",
+                room_code, user_name, current_round
+            );
+
+            room.broadcast_event(
+                room_code,
+                RpsEvent::ThrowMissed {
+                    round: current_round,
+                    user_name: user_name.clone(),
+                },
+            );
+
+            match self.settings.missed_throw {
+                // In a bracket that's the whole match
+                MissedThrow::Forfeit if self.bracket.is_some() => {
+                    forfeited.insert(user_name);
+                }
+                MissedThrow::Forfeit => {
+                    let player = &mut room.users[i];
+                    player.score.record(&RoundOutcome::Lose);
+                    player.rounds_played += 1;
+                }
+                MissedThrow::RandomThrow => {
+                    let hand = self.rule_set.random_hand();
+                    self.submit_hand(room, room_code, i, hand)
+                }
+            }
+        }
+
+        self.forfeit_matches(room, room_code, &forfeited);
+        self.advance_round(room, room_code);
+    }
+
+    /// The player still owes a hand for the current round
+    fn awaiting_throw(&self, room: &GameLobbyState, u: &UserServerSideState) -> bool {
+        if self.hidden_hands.contains_key(&u.user_name) {
+            return false;
+        }
+
+        match self.bracket {
+            Some(_) => self.opponents.contains_key(&u.user_name),
+            None => u.rounds_played < room.current_round,
+        }
+    }
+
+    /// Hand the bracket matches of players who can't play them to their
+    /// opponents. If neither player can, the better seed goes through.
+    fn forfeit_matches(&mut self, room: &GameLobbyState, room_code: &str, out: &HashSet<String>) {
+        let bracket = match self.bracket.as_ref() {
+            Some(bracket) => bracket,
+            None => return,
+        };
+
+        let decided: Vec<(String, String)> = self
+            .opponents
+            .iter()
+            .filter(|(a, b)| a < b && (out.contains(*a) || out.contains(*b)))
+            .map(|(a, b)| match (out.contains(a), out.contains(b)) {
+                (true, false) => (b.clone(), a.clone()),
+                (false, true) => (a.clone(), b.clone()),
+                _ if bracket.better_seed(a, b) == a => (a.clone(), b.clone()),
+                _ => (b.clone(), a.clone()),
+            })
+            .collect();
+
+        for (winner, loser) in decided {
+            info!("({}) {} wins by forfeit over {}", room_code, winner, loser);
+            self.decide_match(room, room_code, &winner, &loser);
+        }
+    }
+
+    fn decide_match(&mut self, room: &GameLobbyState, room_code: &str, winner: &str, loser: &str) {
+        for user_name in [winner, loser].iter() {
+            self.opponents.remove(*user_name);
+            self.hidden_hands.remove(*user_name);
+        }
+
+        if let Some(bracket) = self.bracket.as_mut() {
+            bracket.record_win(winner, loser);
+        }
+
+        if let Some(update) = self.bracket_update() {
+            room.broadcast_event(room_code, update);
+        }
+    }
+
+    /// Take a player's hand for the round. In PvP it stays hidden until their
+    /// opponent throws, then both are revealed together.
+    fn submit_hand(
+        &mut self,
+        room: &mut GameLobbyState,
+        room_code: &str,
+        user_index: usize,
+        hand: Hand,
+    ) {
+        let user_name = room.users[user_index].user_name.clone();
+
+        let opponent = match self.opponents.get(&user_name) {
+            Some(opponent) => opponent.clone(),
+            None => return self.resolve_throw(room, room_code, user_index, hand),
+        };
+
+        match (
+            self.hidden_hands.remove(&opponent),
+            room.player_index(&opponent),
+        ) {
+            (Some(opponent_hand), Some(opponent_index)) => self.resolve_pair(
+                room,
+                room_code,
+                user_index,
+                hand,
+                opponent_index,
+                opponent_hand,
+            ),
+            _ => {
+                info!(
+                    "({}) {} is waiting on {} to throw",
+                    room_code, user_name, opponent
+                );
+
+                self.hidden_hands.insert(user_name.clone(), hand);
+                room.broadcast_event(
+                    room_code,
+                    RpsEvent::HandLocked {
+                        round: room.current_round,
+                        user_name,
+                    },
+                );
+            }
+        }
+    }
+
+    /// A hidden hand whose opponent can't throw this round anymore, because
+    /// they left or forfeited, is played against the server instead
+    fn settle_unmatched(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        let current_round = room.current_round;
+
+        let unmatched: Vec<String> = self
+            .hidden_hands
+            .keys()
+            .filter(|user_name| {
+                match self
+                    .opponents
+                    .get(*user_name)
+                    .and_then(|opponent| room.player_index(opponent))
+                {
+                    Some(i) => {
+                        !room.users[i].connected || room.users[i].rounds_played >= current_round
+                    }
+                    None => true,
+                }
+            })
+            .cloned()
+            .collect();
+
+        for user_name in unmatched {
+            if let (Some(hand), Some(i)) = (
+                self.hidden_hands.remove(&user_name),
+                room.player_index(&user_name),
+            ) {
+                self.resolve_throw(room, room_code, i, hand);
+            }
+        }
+    }
+
+    /// Play a player's hand against the server
+    fn resolve_throw(
+        &mut self,
+        room: &mut GameLobbyState,
+        room_code: &str,
+        user_index: usize,
+        hand: Hand,
+    ) {
+        let server_hand = match self.settings.audience_mode {
+            AudienceMode::Predict => self.settings.server_strategy.choose(
+                &self.rule_set,
+                &room.throw_history,
+                &room.users[user_index].user_name,
+            ),
+            AudienceMode::CrowdVsPlayers => self.crowd_hand(),
+        };
+
+        info!(
+            "({}) {} threw {} against the server's {}",
+            room_code, room.users[user_index].user_name, hand, server_hand
+        );
+
+        self.record_throw(room, room_code, user_index, hand, server_hand, None);
+    }
+
+    /// Reveal a PvP pair's hands and settle the round between them
+    fn resolve_pair(
+        &mut self,
+        room: &mut GameLobbyState,
+        room_code: &str,
+        first: usize,
+        first_hand: Hand,
+        second: usize,
+        second_hand: Hand,
+    ) {
+        let first_name = room.users[first].user_name.clone();
+        let second_name = room.users[second].user_name.clone();
+
+        info!(
+            "({}) {} threw {} against {}'s {}",
+            room_code, first_name, first_hand, second_name, second_hand
+        );
+
+        let first_outcome = self.rule_set.outcome(&first_hand, &second_hand);
+
+        self.record_throw(
+            room,
+            room_code,
+            first,
+            first_hand.clone(),
+            second_hand.clone(),
+            Some(second_name.clone()),
+        );
+        self.record_throw(
+            room,
+            room_code,
+            second,
+            second_hand,
+            first_hand,
+            Some(first_name.clone()),
+        );
+
+        if self.bracket.is_none() {
+            return;
+        }
+
+        // A drawn match is thrown again next round, the rest are settled
+        match first_outcome {
+            RoundOutcome::Win => self.decide_match(room, room_code, &first_name, &second_name),
+            RoundOutcome::Lose => self.decide_match(room, room_code, &second_name, &first_name),
+            RoundOutcome::Draw => {
+                self.opponents.remove(&first_name);
+                self.opponents.remove(&second_name);
+
+                if let Some(bracket) = self.bracket.as_mut() {
+                    bracket.record_draw(&first_name, &second_name);
+                }
+            }
+        }
+    }
+
+    /// Score a throw and let the room know how it went
+    fn record_throw(
+        &mut self,
+        room: &mut GameLobbyState,
+        room_code: &str,
+        user_index: usize,
+        hand: Hand,
+        against: Hand,
+        opponent: Option<String>,
+    ) {
+        let current_round = room.current_round;
+        let outcome = self.rule_set.outcome(&hand, &against);
+
+        let user_name = room.users[user_index].user_name.clone();
+
+        // Hands are only learned once they're out in the open, so the server
+        // never sees one before it throws its own
+        room.throw_history.learn(&user_name, &hand);
+
+        let player = &mut room.users[user_index];
+
+        player.score.record(&outcome);
+        player.rounds_played += 1;
+
+        // The audience's votes were about this throw, so tell them how they did
+        let voted_hand = match self.settings.audience_mode {
+            AudienceMode::Predict if self.spotlight.as_ref() == Some(&user_name) => {
+                Some(hand.clone())
+            }
+            AudienceMode::CrowdVsPlayers if opponent.is_none() => Some(against.clone()),
+            _ => None,
+        };
+
+        room.broadcast_event(
+            room_code,
+            RpsEvent::RoundResult {
+                user_name: user_name.clone(),
+                player_hand: hand,
+                server_hand: against,
+                outcome,
+                opponent,
+            },
+        );
+
+        if let Some(voted_hand) = voted_hand {
+            room.broadcast_event(
+                room_code,
+                RpsEvent::AudienceVoteResult {
+                    round: current_round,
+                    mode: self.settings.audience_mode.clone(),
+                    user_name,
+                    hand: voted_hand,
+                    votes: self.vote_tally(),
+                },
+            );
+        }
+    }
+
+    fn vote_open_update(&self, room: &GameLobbyState) -> RpsEvent {
+        RpsEvent::AudienceVoteOpen {
+            round: room.current_round,
+            mode: self.settings.audience_mode.clone(),
+            spotlight: self.spotlight.clone(),
+        }
+    }
+
+    /// Share of the audience's votes going to each hand
+    fn vote_tally(&self) -> Vec<VoteShare> {
+        let total = self.votes.len();
+
+        self.rule_set
+            .hands()
+            .map(|hand| {
+                let votes = self.votes.values().filter(|v| **v == hand).count();
+                let percent = if total == 0 {
+                    0.0
+                } else {
+                    votes as f32 * 100.0 / total as f32
+                };
+
+                VoteShare {
+                    hand,
+                    votes,
+                    percent,
+                }
+            })
+            .collect()
+    }
+
+    /// The crowd's most popular hand, picking at random between ties. With no
+    /// votes in, the server picks for them. The first pick of the round stands
+    /// for every player in it.
+    fn crowd_hand(&mut self) -> Hand {
+        if let Some(hand) = self.crowd_pick.as_ref() {
+            return hand.clone();
+        }
+
+        let tally = self.vote_tally();
+        let most_votes = tally.iter().map(|v| v.votes).max().unwrap_or(0);
+
+        let hand = if most_votes == 0 {
+            self.rule_set.random_hand()
+        } else {
+            let leaders: Vec<&VoteShare> = tally.iter().filter(|v| v.votes == most_votes).collect();
+            leaders[thread_rng().gen_range(0, leaders.len())]
+                .hand
+                .clone()
+        };
+
+        self.crowd_pick = Some(hand.clone());
+        hand
+    }
+
+    /// Everyone still connected has thrown their hand for the current round
+    fn round_complete(&self, room: &GameLobbyState) -> bool {
+        // Bracket pairs drop out of `opponents` once they've played
+        if self.bracket.is_some() {
+            return self.opponents.is_empty();
+        }
+
+        room.players()
+            .filter(|u| u.connected)
+            .all(|u| u.rounds_played >= room.current_round)
+    }
+
+    /// Move on to the next round once everyone has thrown, or finish the match
+    /// after the last one. Everyone gets a score update either way.
+    fn advance_round(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        if self.bracket.is_some() {
+            return self.advance_bracket(room, room_code);
+        }
+
+        self.settle_unmatched(room, room_code);
+
+        if !self.round_complete(room) {
+            room.broadcast(&room.score_update(room_code));
+            return;
+        }
+
+        if room.current_round < room.total_rounds {
+            room.current_round += 1;
+            room.broadcast(&room.score_update(room_code));
+            self.open_round(room, room_code);
+        } else {
+            self.finish();
+        }
+    }
+
+    /// Move a bracket game along once every match being played has had its
+    /// throw, drawing up the next bracket round once they've all been won
+    fn advance_bracket(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        // Leaving in the middle of a match loses it
+        let gone: HashSet<String> = self
+            .opponents
+            .keys()
+            .filter(|user_name| {
+                room.player_index(user_name)
+                    .is_none_or(|i| !room.users[i].connected)
+            })
+            .cloned()
+            .collect();
+        self.forfeit_matches(room, room_code, &gone);
+
+        if !self.round_complete(room) {
+            room.broadcast(&room.score_update(room_code));
+            return;
+        }
+
+        room.current_round += 1;
+
+        let connected: HashSet<String> = room
+            .players()
+            .filter(|u| u.connected)
+            .map(|u| u.user_name.clone())
+            .collect();
+
+        let bracket = self.bracket.as_mut().expect("bracket game has a bracket");
+        if bracket.round_decided() {
+            bracket.next_round(|user_name| connected.contains(user_name));
+            info!("({}) Bracket round {} drawn up", room_code, bracket.round);
+
+            if let Some(update) = self.bracket_update() {
+                room.broadcast_event(room_code, update);
+            }
+        }
+
+        if self.bracket.as_ref().is_some_and(|b| b.finished()) {
+            self.finish();
+        } else {
+            room.broadcast(&room.score_update(room_code));
+            self.open_round(room, room_code);
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! The moves a room plays with and which of them beat which.
+//!
+//! Every built-in variant, and any the host makes up, is a circle of an odd
+//! number of moves where each move beats the half of the circle just before
+//! it. With three moves that's plain rock-paper-scissors, and every move wins
+//! and loses against the same number of others.
+
+use std::fmt;
+
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+/// Longest name a host-defined move can have
+pub const MAX_MOVE_LEN: usize = 16;
+/// Most moves a host-defined variant can have, so the buttons still fit
+pub const MAX_MOVES: usize = 15;
+
+/// A move, by its name in the room's rule-set
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct Hand(String);
+
+impl Hand {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Hand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The result of a single throw, from the player's point of view
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub enum RoundOutcome {
+    Win,
+    Lose,
+    Draw,
+}
+
+/// Which rule-set a host wants their room to play with
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub enum Variant {
+    /// Rock, paper, scissors
+    #[default]
+    Classic,
+    /// Rock, paper, scissors, lizard, Spock
+    RockPaperScissorsLizardSpock,
+    /// The host's own moves, in order around the circle
+    Custom { moves: Vec<String> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleSetError {
+    TooFewMoves,
+    TooManyMoves,
+    /// Somebody would always be able to dodge a loss with an even number of moves
+    EvenMoves,
+    BadMoveName(String),
+    DuplicateMove(String),
+}
+
+impl fmt::Display for RuleSetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleSetError::TooFewMoves => write!(f, "A rule-set needs at least 3 moves"),
+            RuleSetError::TooManyMoves => {
+                write!(f, "A rule-set can have at most {} moves", MAX_MOVES)
+            }
+            RuleSetError::EvenMoves => write!(f, "A rule-set needs an odd number of moves"),
+            RuleSetError::BadMoveName(name) => write!(
+                f,
+                "Move \"{}\" must be 1 to {} letters, numbers or dashes",
+                name, MAX_MOVE_LEN
+            ),
+            RuleSetError::DuplicateMove(name) => write!(f, "Move \"{}\" is listed twice", name),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RuleSet {
+    pub variant: Variant,
+    pub moves: Vec<String>,
+    /// For each move, the moves it beats, by index into `moves`
+    pub beats: Vec<Vec<usize>>,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet::new(Variant::Classic).expect("Classic rule-set is valid")
+    }
+}
+
+impl RuleSet {
+    pub fn new(variant: Variant) -> Result<Self, RuleSetError> {
+        let moves: Vec<String> = match &variant {
+            Variant::Classic => vec!["Rock", "Paper", "Scissors"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            Variant::RockPaperScissorsLizardSpock => {
+                vec!["Rock", "Spock", "Paper", "Lizard", "Scissors"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect()
+            }
+            Variant::Custom { moves } => moves.iter().map(|m| m.trim().to_string()).collect(),
+        };
+
+        if moves.len() < 3 {
+            return Err(RuleSetError::TooFewMoves);
+        }
+        if moves.len() > MAX_MOVES {
+            return Err(RuleSetError::TooManyMoves);
+        }
+        if moves.len() % 2 != 1 {
+            return Err(RuleSetError::EvenMoves);
+        }
+
+        for (i, name) in moves.iter().enumerate() {
+            if name.is_empty()
+                || name.len() > MAX_MOVE_LEN
+                || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                return Err(RuleSetError::BadMoveName(name.clone()));
+            }
+
+            if moves[..i].iter().any(|m| m.eq_ignore_ascii_case(name)) {
+                return Err(RuleSetError::DuplicateMove(name.clone()));
+            }
+        }
+
+        let n = moves.len();
+        let beats = (0..n)
+            .map(|i| (1..=n / 2).map(|back| (i + n - back) % n).collect())
+            .collect();
+
+        let variant = match variant {
+            Variant::Custom { .. } => Variant::Custom {
+                moves: moves.clone(),
+            },
+            v => v,
+        };
+
+        Ok(RuleSet {
+            variant,
+            moves,
+            beats,
+        })
+    }
+
+    fn index_of(&self, hand: &Hand) -> Option<usize> {
+        self.moves.iter().position(|m| *m == hand.0)
+    }
+
+    /// The move with this name, ignoring case, if the rule-set has one
+    pub fn hand(&self, name: &str) -> Option<Hand> {
+        self.moves
+            .iter()
+            .find(|m| m.eq_ignore_ascii_case(name.trim()))
+            .map(|m| Hand(m.clone()))
+    }
+
+    pub fn hands(&self) -> impl Iterator<Item = Hand> + '_ {
+        self.moves.iter().map(|m| Hand(m.clone()))
+    }
+
+    pub fn random_hand(&self) -> Hand {
+        Hand(self.moves[thread_rng().gen_range(0, self.moves.len())].clone())
+    }
+
+    /// Returns true if `hand` wins against `other`
+    pub fn beats(&self, hand: &Hand, other: &Hand) -> bool {
+        match (self.index_of(hand), self.index_of(other)) {
+            (Some(a), Some(b)) => self.beats[a].contains(&b),
+            _ => false,
+        }
+    }
+
+    /// How `hand` fares against `other`
+    pub fn outcome(&self, hand: &Hand, other: &Hand) -> RoundOutcome {
+        if self.beats(hand, other) {
+            RoundOutcome::Win
+        } else if self.beats(other, hand) {
+            RoundOutcome::Lose
+        } else {
+            RoundOutcome::Draw
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(moves: &[&str]) -> Result<RuleSet, RuleSetError> {
+        RuleSet::new(Variant::Custom {
+            moves: moves.iter().map(|m| m.to_string()).collect(),
+        })
+    }
+
+    fn hand(rule_set: &RuleSet, name: &str) -> Hand {
+        rule_set.hand(name).unwrap()
+    }
+
+    /// Checks `winner` beats `loser` from both sides, and that nothing else does
+    fn assert_rules(rule_set: &RuleSet, rules: &[(&str, &str)]) {
+        for a in rule_set.hands() {
+            for b in rule_set.hands() {
+                let expected = if rules.contains(&(a.as_str(), b.as_str())) {
+                    RoundOutcome::Win
+                } else if rules.contains(&(b.as_str(), a.as_str())) {
+                    RoundOutcome::Lose
+                } else {
+                    RoundOutcome::Draw
+                };
+
+                assert_eq!(rule_set.outcome(&a, &b), expected, "{} against {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn plays_classic_rules() {
+        let rule_set = RuleSet::default();
+
+        assert_rules(
+            &rule_set,
+            &[
+                ("Rock", "Scissors"),
+                ("Scissors", "Paper"),
+                ("Paper", "Rock"),
+            ],
+        );
+    }
+
+    #[test]
+    fn plays_rock_paper_scissors_lizard_spock_rules() {
+        let rule_set = RuleSet::new(Variant::RockPaperScissorsLizardSpock).unwrap();
+
+        assert_rules(
+            &rule_set,
+            &[
+                ("Scissors", "Paper"),
+                ("Paper", "Rock"),
+                ("Rock", "Lizard"),
+                ("Lizard", "Spock"),
+                ("Spock", "Scissors"),
+                ("Scissors", "Lizard"),
+                ("Lizard", "Paper"),
+                ("Paper", "Spock"),
+                ("Spock", "Rock"),
+                ("Rock", "Scissors"),
+            ],
+        );
+    }
+
+    #[test]
+    fn custom_moves_beat_the_half_of_the_circle_before_them() {
+        let rule_set = custom(&["A", "B", "C", "D", "E"]).unwrap();
+
+        assert_rules(
+            &rule_set,
+            &[
+                ("A", "E"),
+                ("A", "D"),
+                ("B", "A"),
+                ("B", "E"),
+                ("C", "B"),
+                ("C", "A"),
+                ("D", "C"),
+                ("D", "B"),
+                ("E", "D"),
+                ("E", "C"),
+            ],
+        );
+    }
+
+    #[test]
+    fn tidies_custom_move_names() {
+        let rule_set = custom(&[" Fire", "Water ", "Grass"]).unwrap();
+
+        assert_eq!(rule_set.moves, vec!["Fire", "Water", "Grass"]);
+        assert_eq!(
+            rule_set.variant,
+            Variant::Custom {
+                moves: rule_set.moves.clone()
+            }
+        );
+        assert_eq!(hand(&rule_set, " water").as_str(), "Water");
+        assert!(rule_set.hand("Rock").is_none());
+    }
+
+    #[test]
+    fn needs_an_odd_number_of_moves() {
+        assert_eq!(custom(&["A", "B"]).unwrap_err(), RuleSetError::TooFewMoves);
+        assert_eq!(
+            custom(&["A", "B", "C", "D"]).unwrap_err(),
+            RuleSetError::EvenMoves
+        );
+    }
+
+    #[test]
+    fn limits_how_many_moves_there_are() {
+        let names: Vec<String> = (0..MAX_MOVES + 2).map(|i| format!("M{}", i)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+
+        assert!(custom(&names[..MAX_MOVES]).is_ok());
+        assert_eq!(
+            custom(&names[..MAX_MOVES + 2]).unwrap_err(),
+            RuleSetError::TooManyMoves
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_moves_ignoring_case() {
+        assert_eq!(
+            custom(&["Fire", "Water", "FIRE"]).unwrap_err(),
+            RuleSetError::DuplicateMove("FIRE".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_bad_move_names() {
+        let too_long = "a".repeat(MAX_MOVE_LEN + 1);
+
+        for bad in ["", "Two words", "Fire!", "Café", too_long.as_str()] {
+            assert_eq!(
+                custom(&["Fire", bad, "Water"]).unwrap_err(),
+                RuleSetError::BadMoveName(bad.to_string())
+            );
+        }
+
+        assert!(custom(&["Fire", "Ice-2", "Water"]).is_ok());
+    }
+}
@@ -0,0 +1,303 @@
+//! How the server picks its hand when players throw against it.
+//!
+//! Apart from `Random`, every strategy studies the hands each player has
+//! thrown so far and plays whatever does best against what it expects them to
+//! throw next, so players who fall into a habit start losing to it.
+
+use std::collections::HashMap;
+
+use rand::{seq::SliceRandom, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::rule_set::{Hand, RoundOutcome, RuleSet};
+
+/// Longest run of throws the Markov strategy looks back over
+pub const MAX_MARKOV_HISTORY: usize = 4;
+/// Throws remembered for each player, so old habits are forgotten
+const MAX_REMEMBERED: usize = 100;
+
+/// What the server plays against players
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub enum ServerStrategy {
+    /// Any move, picked at random
+    #[default]
+    Random,
+    /// Counter the moves the player throws most
+    Frequency,
+    /// Counter whatever the player threw after their last `history` throws
+    /// the other times they threw them
+    Markov { history: usize },
+    /// Play at random some of the time, and otherwise use whichever way of
+    /// reading the player has been right most often
+    Mixed { difficulty: Difficulty },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// How often the server tries to read the player instead of playing at random
+    fn read_chance(&self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.3,
+            Difficulty::Normal => 0.6,
+            Difficulty::Hard => 0.9,
+        }
+    }
+}
+
+/// A way of guessing a player's next throw from their earlier ones
+#[derive(Debug, Clone, Copy)]
+enum Predictor {
+    Frequency,
+    /// Looks back over this many throws
+    Markov(usize),
+}
+
+/// What `Mixed` picks between, shortest memory first
+const MIXED_PREDICTORS: [Predictor; 3] = [
+    Predictor::Frequency,
+    Predictor::Markov(1),
+    Predictor::Markov(2),
+];
+
+impl Predictor {
+    /// How often each move is expected to come next, going by `throws`
+    fn predict<'a>(&self, throws: &'a [Hand]) -> HashMap<&'a Hand, usize> {
+        match *self {
+            Predictor::Frequency => count(throws.iter()),
+            // Fall back to shorter runs until one has come up before
+            Predictor::Markov(history) => (1..=history.min(throws.len().saturating_sub(1)))
+                .rev()
+                .map(|n| {
+                    let recent = &throws[throws.len() - n..];
+                    count(
+                        throws
+                            .windows(n + 1)
+                            .filter(|w| w[..n] == *recent)
+                            .map(|w| &w[n]),
+                    )
+                })
+                .find(|counts| !counts.is_empty())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn count<'a, I: Iterator<Item = &'a Hand>>(hands: I) -> HashMap<&'a Hand, usize> {
+    let mut counts = HashMap::new();
+    for hand in hands {
+        *counts.entry(hand).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// What the server has learned about a player
+#[derive(Debug, Clone, Default)]
+struct PlayerHistory {
+    /// Their throws, oldest first
+    throws: Vec<Hand>,
+    /// How many throws each of `MIXED_PREDICTORS` has called right
+    hits: [usize; MIXED_PREDICTORS.len()],
+}
+
+/// Everything the server has learned about the players in a room, by user
+/// name. Rooms keep it from one game to the next, whatever the server played.
+#[derive(Debug, Clone, Default)]
+pub struct ThrowHistory {
+    players: HashMap<String, PlayerHistory>,
+}
+
+impl ThrowHistory {
+    /// Remember a hand the player has thrown
+    pub fn learn(&mut self, user_name: &str, hand: &Hand) {
+        let history = self.players.entry(user_name.to_string()).or_default();
+
+        for (i, predictor) in MIXED_PREDICTORS.iter().enumerate() {
+            let predicted = predictor.predict(&history.throws);
+            let best_guess = predicted.iter().max_by_key(|(_, &times)| times);
+
+            if best_guess.is_some_and(|(guess, _)| *guess == hand) {
+                history.hits[i] += 1;
+            }
+        }
+
+        history.throws.push(hand.clone());
+        if history.throws.len() > MAX_REMEMBERED {
+            history.throws.remove(0);
+        }
+    }
+}
+
+impl ServerStrategy {
+    /// The server's hand against `user_name`, picked without seeing theirs
+    pub fn choose(&self, rule_set: &RuleSet, history: &ThrowHistory, user_name: &str) -> Hand {
+        let history = match history.players.get(user_name) {
+            Some(history) => history,
+            None => return rule_set.random_hand(),
+        };
+
+        let predictor = match self {
+            ServerStrategy::Random => None,
+            ServerStrategy::Frequency => Some(Predictor::Frequency),
+            ServerStrategy::Markov { history } => {
+                Some(Predictor::Markov((*history).clamp(1, MAX_MARKOV_HISTORY)))
+            }
+            ServerStrategy::Mixed { difficulty } => {
+                if thread_rng().gen_bool(difficulty.read_chance()) {
+                    // Ties go to the longest memory
+                    (0..MIXED_PREDICTORS.len())
+                        .max_by_key(|&i| history.hits[i])
+                        .map(|i| MIXED_PREDICTORS[i])
+                } else {
+                    None
+                }
+            }
+        };
+
+        match predictor {
+            Some(predictor) => counter(rule_set, &predictor.predict(&history.throws)),
+            None => rule_set.random_hand(),
+        }
+    }
+}
+
+/// The move that does best against the `expected` throws, picked at random
+/// from the equally good ones
+fn counter(rule_set: &RuleSet, expected: &HashMap<&Hand, usize>) -> Hand {
+    let scored: Vec<(Hand, i64)> = rule_set
+        .hands()
+        .map(|hand| {
+            let score = expected
+                .iter()
+                .map(|(other, &times)| match rule_set.outcome(&hand, other) {
+                    RoundOutcome::Win => times as i64,
+                    RoundOutcome::Lose => -(times as i64),
+                    RoundOutcome::Draw => 0,
+                })
+                .sum();
+            (hand, score)
+        })
+        .collect();
+
+    let best = scored.iter().map(|(_, score)| *score).max().unwrap_or(0);
+    let best_hands: Vec<Hand> = scored
+        .into_iter()
+        .filter(|(_, score)| *score == best)
+        .map(|(hand, _)| hand)
+        .collect();
+
+    best_hands
+        .choose(&mut thread_rng())
+        .cloned()
+        .unwrap_or_else(|| rule_set.random_hand())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hand(name: &str) -> Hand {
+        RuleSet::default().hand(name).unwrap()
+    }
+
+    fn history(throws: &[&str]) -> ThrowHistory {
+        let mut history = ThrowHistory::default();
+        for name in throws {
+            history.learn("Alice", &hand(name));
+        }
+        history
+    }
+
+    /// What `strategy` plays against Alice, checked enough times that a lucky
+    /// random pick wouldn't pass
+    fn assert_counters(strategy: ServerStrategy, history: &ThrowHistory, expected: &str) {
+        let rule_set = RuleSet::default();
+
+        for _ in 0..20 {
+            assert_eq!(
+                strategy.choose(&rule_set, history, "Alice"),
+                hand(expected),
+                "{:?}",
+                strategy
+            );
+        }
+    }
+
+    #[test]
+    fn counters_a_player_who_only_throws_rock() {
+        let history = history(&["Rock"; 5]);
+
+        assert_counters(ServerStrategy::Frequency, &history, "Paper");
+        for n in [0, 1, 3, MAX_MARKOV_HISTORY + 5] {
+            assert_counters(ServerStrategy::Markov { history: n }, &history, "Paper");
+        }
+    }
+
+    #[test]
+    fn follows_a_player_who_alternates() {
+        let markov = ServerStrategy::Markov { history: 1 };
+
+        // Paper comes after Rock, so the server throws Scissors
+        let after_rock = history(&["Rock", "Paper", "Rock", "Paper", "Rock"]);
+        assert_counters(markov.clone(), &after_rock, "Scissors");
+
+        // And Rock comes after Paper
+        let after_paper = history(&["Rock", "Paper", "Rock", "Paper"]);
+        assert_counters(markov, &after_paper, "Paper");
+    }
+
+    #[test]
+    fn falls_back_to_shorter_runs_it_has_seen_before() {
+        let throws = [hand("Rock"), hand("Paper"), hand("Scissors"), hand("Rock")];
+
+        // Neither Paper, Scissors, Rock nor Scissors, Rock has come up before,
+        // but Rock has, and Paper came after it
+        let predicted = Predictor::Markov(3).predict(&throws);
+        assert_eq!(predicted.len(), 1);
+        assert_eq!(predicted.get(&hand("Paper")), Some(&1));
+
+        assert!(Predictor::Markov(1).predict(&throws[..1]).is_empty());
+
+        let history = history(&["Rock", "Paper", "Scissors", "Rock"]);
+        assert_counters(ServerStrategy::Markov { history: 3 }, &history, "Scissors");
+    }
+
+    #[test]
+    fn plays_anything_against_players_it_doesnt_know() {
+        let rule_set = RuleSet::default();
+        let history = history(&["Rock"; 5]);
+
+        for _ in 0..20 {
+            let server_hand = ServerStrategy::Frequency.choose(&rule_set, &history, "Bob");
+            assert!(rule_set.hands().any(|h| h == server_hand));
+        }
+    }
+
+    #[test]
+    fn forgets_old_throws() {
+        let mut throws = vec!["Rock"; MAX_REMEMBERED / 2];
+        throws.extend(vec!["Scissors"; MAX_REMEMBERED]);
+        let history = history(&throws);
+
+        let remembered = &history.players["Alice"].throws;
+        assert_eq!(remembered.len(), MAX_REMEMBERED);
+        assert!(remembered.iter().all(|h| *h == hand("Scissors")));
+
+        assert_counters(ServerStrategy::Frequency, &history, "Rock");
+    }
+
+    #[test]
+    fn scores_each_way_of_reading_the_player() {
+        let history = history(&["Rock"; 10]);
+
+        // Nothing can be predicted from no throws, and the Markov predictors
+        // need a throw to look back on as well
+        assert_eq!(history.players["Alice"].hits, [9, 8, 8]);
+    }
+}
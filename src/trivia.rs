@@ -0,0 +1,466 @@
+//! Trivia, asked from a question pack the host picks.
+//!
+//! Every player gets the same multiple choice question at once, and has until
+//! the clock runs out to answer it. Right answers score more the faster they
+//! come in. Once everyone has answered, or time's up, the right answer is
+//! revealed along with how the room answered, and the next question follows
+//! after a short pause.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::info;
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+
+use crate::game::{self, Game, GameAction, GameEvent};
+use crate::question_pack::{Question, QuestionPack, QuestionPackError};
+use crate::{ErrorCode, GameLobbyError, GameLobbyState};
+
+/// Time players get for a question when neither the pack nor the room says
+const DEFAULT_QUESTION_TIME: Duration = Duration::from_secs(20);
+/// How long the answer stays up before the next question is asked
+const REVEAL_TIME: Duration = Duration::from_secs(5);
+/// Points for answering right the moment the question is asked. Right answers
+/// are worth at least half of this, however late they come in.
+const MAX_POINTS: usize = 1000;
+
+/// How the host set up the quiz
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TriviaSettings {
+    /// Name of the question pack to ask from
+    pub pack: String,
+    /// Ask the pack's questions in a random order
+    #[serde(default)]
+    pub shuffle: bool,
+    /// The pack itself, once it's been read. Its answers aren't for sending
+    /// to players along with the settings.
+    #[serde(skip)]
+    questions: Arc<QuestionPack>,
+}
+
+impl TriviaSettings {
+    pub async fn load_pack(mut self, dir: &Path) -> Result<Self, GameLobbyError> {
+        let pack = QuestionPack::load(dir, &self.pack).await.map_err(|e| {
+            let code = match e {
+                QuestionPackError::BadName(_) | QuestionPackError::NotFound(_) => {
+                    ErrorCode::UnknownQuestionPack
+                }
+                _ => ErrorCode::InvalidQuestionPack,
+            };
+            GameLobbyError::new(code, e.to_string())
+        })?;
+
+        self.questions = Arc::new(pack);
+        Ok(self)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AnswerShare {
+    pub answer: String,
+    pub votes: usize,
+    pub percent: f32,
+}
+
+/// How a player did on a question
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AnswerResult {
+    pub user_name: String,
+    /// None if they didn't answer in time
+    pub answer: Option<usize>,
+    pub correct: bool,
+    pub points: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum TriviaAction {
+    /// A player's answer to the current question, by index into its answers.
+    /// Answers are final.
+    Answer { answer: usize },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum TriviaEvent {
+    QuestionStarted {
+        round: usize,
+        total_rounds: usize,
+        question: String,
+        answers: Vec<String>,
+        seconds_left: u64,
+        /// When the question runs out of time, in milliseconds since the Unix epoch
+        deadline: Option<u64>,
+    },
+    /// A player has answered. What they answered stays hidden until the reveal.
+    AnswerLocked { round: usize, user_name: String },
+    QuestionResult {
+        round: usize,
+        question: String,
+        /// The right answer, by index into the question's answers
+        correct: usize,
+        /// How many players went for each answer
+        answers: Vec<AnswerShare>,
+        results: Vec<AnswerResult>,
+    },
+}
+
+/// A player's answer to the current question
+#[derive(Debug, Clone)]
+struct Answer {
+    answer: usize,
+    /// How long they took to answer
+    time_taken: Duration,
+}
+
+#[derive(Debug, Clone)]
+enum Phase {
+    /// Waiting on the quiz to start
+    Idle,
+    Asking {
+        started: Instant,
+        deadline: Instant,
+    },
+    /// Showing the answer to the last question until the next one is asked
+    Revealing {
+        until: Instant,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct TriviaGame {
+    settings: TriviaSettings,
+    /// The questions asked this match, in the order they're asked
+    questions: Vec<Question>,
+    phase: Phase,
+    /// Answers to the current question, by user name
+    answers: HashMap<String, Answer>,
+    /// How the last question went, for anyone joining during the reveal
+    last_result: Option<TriviaEvent>,
+    finished: bool,
+}
+
+impl Game for TriviaGame {
+    fn start(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        self.questions = self.settings.questions.questions.clone();
+        if self.settings.shuffle {
+            self.questions.shuffle(&mut thread_rng());
+        }
+
+        // The host picked how many questions to ask, as long as the pack has them
+        self.questions.truncate(room.total_rounds);
+        room.total_rounds = self.questions.len();
+
+        room.broadcast(&room.game_start_update(room_code));
+        room.broadcast(&room.score_update(room_code));
+
+        if self.questions.is_empty() {
+            self.finish();
+        } else {
+            self.ask_question(room, room_code);
+        }
+    }
+
+    fn handle_action(
+        &mut self,
+        room: &mut GameLobbyState,
+        room_code: &str,
+        user_index: usize,
+        action: GameAction,
+    ) -> Result<(), GameLobbyError> {
+        let action = match action {
+            GameAction::Trivia(action) => action,
+            _ => return Err(GameLobbyError::wrong_game()),
+        };
+
+        match action {
+            TriviaAction::Answer { answer } => self.answer(room, room_code, user_index, answer),
+        }
+    }
+
+    fn tick(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        let now = Instant::now();
+
+        match self.phase {
+            Phase::Asking { deadline, .. } if now >= deadline => self.reveal(room, room_code),
+            Phase::Revealing { until } if now >= until => {
+                room.current_round += 1;
+                room.broadcast(&room.score_update(room_code));
+                self.ask_question(room, room_code);
+            }
+            _ => {}
+        }
+    }
+
+    /// Don't let the rest of the room wait on an answer that will never come
+    fn player_left(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        if matches!(self.phase, Phase::Asking { .. }) && self.everyone_answered(room) {
+            self.reveal(room, room_code);
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn snapshot(&self, room: &GameLobbyState) -> Vec<GameEvent> {
+        if self.finished {
+            return Vec::new();
+        }
+
+        match self.phase {
+            Phase::Asking { deadline, .. } => {
+                vec![self.question_started_update(room, deadline).into()]
+            }
+            Phase::Revealing { .. } => self
+                .last_result
+                .clone()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            Phase::Idle => Vec::new(),
+        }
+    }
+}
+
+impl TriviaGame {
+    pub fn new(settings: TriviaSettings) -> Self {
+        TriviaGame {
+            settings,
+            questions: Vec::new(),
+            phase: Phase::Idle,
+            answers: HashMap::new(),
+            last_result: None,
+            finished: false,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        self.phase = Phase::Idle;
+    }
+
+    fn current_question(&self, room: &GameLobbyState) -> &Question {
+        &self.questions[room.current_round - 1]
+    }
+
+    /// A question's own time wins over the pack's, and the pack's over the room's
+    fn time_limit(&self, room: &GameLobbyState, question: &Question) -> Duration {
+        question
+            .time_limit_secs
+            .or(self.settings.questions.time_limit_secs)
+            .map(Duration::from_secs)
+            .or(room.round_time_limit)
+            .unwrap_or(DEFAULT_QUESTION_TIME)
+    }
+
+    fn ask_question(&mut self, room: &GameLobbyState, room_code: &str) {
+        let started = Instant::now();
+        let deadline = started + self.time_limit(room, self.current_question(room));
+
+        self.answers.clear();
+        self.last_result = None;
+        self.phase = Phase::Asking { started, deadline };
+
+        info!(
+            "({}) Asking question {} of {}",
+            room_code, room.current_round, room.total_rounds
+        );
+
+        room.broadcast_event(room_code, self.question_started_update(room, deadline));
+    }
+
+    fn question_started_update(&self, room: &GameLobbyState, deadline: Instant) -> TriviaEvent {
+        let question = self.current_question(room);
+        let time_left = deadline.saturating_duration_since(Instant::now());
+
+        TriviaEvent::QuestionStarted {
+            round: room.current_round,
+            total_rounds: room.total_rounds,
+            question: question.question.clone(),
+            answers: question.answers.clone(),
+            seconds_left: time_left.as_secs_f64().ceil() as u64,
+            deadline: game::epoch_millis(time_left),
+        }
+    }
+
+    fn answer(
+        &mut self,
+        room: &mut GameLobbyState,
+        room_code: &str,
+        user_index: usize,
+        answer: usize,
+    ) -> Result<(), GameLobbyError> {
+        let player = &room.users[user_index];
+        let user_name = player.user_name.clone();
+
+        if !player.user_type.is_player() {
+            return Err(GameLobbyError::new(
+                ErrorCode::NotAPlayer,
+                "The audience can't answer questions".to_string(),
+            ));
+        }
+
+        // The round clock will reveal the answer on its next tick
+        let started = match self.phase {
+            Phase::Asking { started, deadline } if Instant::now() < deadline => started,
+            _ => {
+                return Err(GameLobbyError::new(
+                    ErrorCode::RoundTimedOut,
+                    format!("Question {} is closed", room.current_round),
+                ))
+            }
+        };
+
+        if self.answers.contains_key(&user_name) {
+            return Err(GameLobbyError::new(
+                ErrorCode::AlreadyPlayedRound,
+                format!(
+                    "{} already answered question {}",
+                    user_name, room.current_round
+                ),
+            ));
+        }
+
+        let answer_count = self.current_question(room).answers.len();
+        if answer >= answer_count {
+            return Err(GameLobbyError::new(
+                ErrorCode::InvalidAnswer,
+                format!(
+                    "Question {} only has {} answers",
+                    room.current_round, answer_count
+                ),
+            ));
+        }
+
+        info!(
+            "({}) {} answered question {}",
+            room_code, user_name, room.current_round
+        );
+
+        self.answers.insert(
+            user_name.clone(),
+            Answer {
+                answer,
+                time_taken: started.elapsed(),
+            },
+        );
+        room.broadcast_event(
+            room_code,
+            TriviaEvent::AnswerLocked {
+                round: room.current_round,
+                user_name,
+            },
+        );
+
+        if self.everyone_answered(room) {
+            self.reveal(room, room_code);
+        }
+
+        Ok(())
+    }
+
+    fn everyone_answered(&self, room: &GameLobbyState) -> bool {
+        room.players()
+            .filter(|u| u.connected)
+            .all(|u| self.answers.contains_key(&u.user_name))
+    }
+
+    /// Right answers score between half and all of `MAX_POINTS`, going down
+    /// the longer the player took
+    fn points(time_taken: Duration, time_limit: Duration) -> usize {
+        let time_left = time_limit.checked_sub(time_taken).unwrap_or_default();
+        let speed_bonus =
+            (MAX_POINTS / 2) as u128 * time_left.as_millis() / time_limit.as_millis().max(1);
+
+        MAX_POINTS / 2 + speed_bonus as usize
+    }
+
+    /// Score everyone's answers, show the room the right one, and move on
+    fn reveal(&mut self, room: &mut GameLobbyState, room_code: &str) {
+        let current_round = room.current_round;
+        let question = self.current_question(room).clone();
+        let time_limit = self.time_limit(room, &question);
+
+        let mut results = Vec::new();
+
+        for player in room.users.iter_mut() {
+            let answer = self.answers.get(&player.user_name);
+
+            // Players who left before answering don't lose anything for it
+            if !player.user_type.is_player() || (answer.is_none() && !player.connected) {
+                continue;
+            }
+
+            let correct = answer.is_some_and(|a| a.answer == question.correct);
+            let points = match answer {
+                Some(a) if correct => TriviaGame::points(a.time_taken, time_limit),
+                _ => 0,
+            };
+
+            if correct {
+                player.score.wins += 1;
+            } else {
+                player.score.losses += 1;
+            }
+            player.score.points += points;
+            player.rounds_played = current_round;
+
+            results.push(AnswerResult {
+                user_name: player.user_name.clone(),
+                answer: answer.map(|a| a.answer),
+                correct,
+                points,
+            });
+        }
+
+        info!(
+            "({}) Question {}: {} of {} right",
+            room_code,
+            current_round,
+            results.iter().filter(|r| r.correct).count(),
+            results.len()
+        );
+
+        let total = self.answers.len();
+        let shares = question
+            .answers
+            .iter()
+            .enumerate()
+            .map(|(i, answer)| {
+                let votes = self.answers.values().filter(|a| a.answer == i).count();
+                let percent = if total == 0 {
+                    0.0
+                } else {
+                    votes as f32 * 100.0 / total as f32
+                };
+
+                AnswerShare {
+                    answer: answer.clone(),
+                    votes,
+                    percent,
+                }
+            })
+            .collect();
+
+        let result = TriviaEvent::QuestionResult {
+            round: current_round,
+            question: question.question,
+            correct: question.correct,
+            answers: shares,
+            results,
+        };
+        room.broadcast_event(room_code, result.clone());
+        self.last_result = Some(result);
+
+        if current_round < room.total_rounds {
+            room.broadcast(&room.score_update(room_code));
+            self.phase = Phase::Revealing {
+                until: Instant::now() + REVEAL_TIME,
+            };
+        } else {
+            self.finish();
+        }
+    }
+}
@@ -5,6 +5,16 @@ let join_game_mode = document.getElementById("join_game_mode");
 let user_name = document.getElementById("user_name");
 let room_code = document.getElementById("room_code");
 let user_type = "Player";
+// The socket waiting on a login, so a rejected login can be retried on a fresh one
+let login_ws = null;
+// Ticks the round's countdown down on screen
+let round_timer = null;
+// Send a hand, an audience vote or a trivia answer on whichever socket we're logged in on
+let throw_hand = null;
+let vote_hand = null;
+let answer_question = null;
+// Anything else done in the game, sent as the game's own action
+let send_action = null;
 
 // Landing
 host_game_mode.onclick = function() {
@@ -12,30 +22,38 @@ host_game_mode.onclick = function() {
     document.getElementById("host_login").style.display = "block";
 }
 
+// Custom rule-sets need their moves typed in
+document.getElementById("host_rule_set").onchange = function() {
+    document.getElementById("host_custom_moves").style.display = (this.value == "Custom") ? "inline" : "none";
+}
+
+// Each game has its own settings to pick. Trivia needs to know which
+// question packs the server has.
+document.getElementById("host_game").onchange = function() {
+    document.getElementById("host_rps_options").style.display = (this.value == "Rps") ? "inline" : "none";
+    document.getElementById("host_trivia_options").style.display = (this.value == "Trivia") ? "inline" : "none";
+    document.getElementById("host_prompt_options").style.display = (this.value == "Prompt") ? "inline" : "none";
+
+    if (this.value == "Trivia" && list_question_packs) {
+        list_question_packs();
+    }
+}
+let list_question_packs = null;
+
 // Select Host a game
 host_connect.onclick = function() {
+    close_login_socket();
     let ws = new WebSocket(uri);
-    let connect_btn = document.getElementById('host_connect');
+    login_ws = ws;
     let user_name_input = document.getElementById('host_user_name');
-    let host_login_div = document.getElementById('host_login');
-    let game_lobby_div = document.getElementById('connected_lobby');
     let host_start_btn = document.getElementById('host_start_game');
-
-    let rock_btn = document.getElementById('rock_btn');
-    let paper_btn = document.getElementById('paper_btn');
-    let scissors_btn = document.getElementById('scissors_btn');
+    let host_restart_btn = document.getElementById('host_restart_game');
+    let host_end_btn = document.getElementById('host_end_game');
 
     ws.onopen = function() {
         ws_connect_status.innerHTML = '<p><em>Connected!</em></p>';
         user_type = "Host";
         join_game_lobby(ws, user_type, user_name_input.value, "" );
-
-        host_login_div.remove();
-        game_lobby_div.style.display = "block";
-        // TODO: Add a button for starting the game
-
-        display_username(user_name_input.value);
-
     };
 
     ws.onmessage = function(msg) {
@@ -44,34 +62,75 @@ host_connect.onclick = function() {
 
     ws.onclose = function() {
         ws_connect_status.getElementsByTagName('em')[0].innerText = 'Disconnected!';
+        resume_session(function(new_ws) { ws = new_ws; }, 0);
     };
 
     host_start_btn.onclick = function () {
 
-        let req =JSON.stringify({ "HostStartGame": { "room_code": room_code.innerHTML }});
+        let rounds = parseInt(document.getElementById('host_rounds_input').value, 10);
+        let audience_mode = document.getElementById('host_audience_mode').value;
+        let missed_throw = document.getElementById('host_missed_throw').value;
+        let game_mode = document.getElementById('host_opponent').value;
+        let server_strategy = document.getElementById('host_server_strategy').value;
+        if (server_strategy == "Markov") {
+            server_strategy = { "Markov": { "history": 2 }};
+        } else if (["Easy", "Normal", "Hard"].includes(server_strategy)) {
+            // Difficulties mix the server's strategies
+            server_strategy = { "Mixed": { "difficulty": server_strategy }};
+        }
+        let game = { "Rps": { "audience_mode": audience_mode, "missed_throw": missed_throw, "game_mode": game_mode, "server_strategy": server_strategy }};
+
+        if (document.getElementById('host_game').value == "Trivia") {
+            game = { "Trivia": {
+                "pack": document.getElementById('host_question_pack').value,
+                "shuffle": document.getElementById('host_shuffle_questions').checked,
+            }};
+        } else if (document.getElementById('host_game').value == "Prompt") {
+            game = { "Prompt": {
+                "submissions": document.getElementById('host_submission_kind').value,
+                "prompts": document.getElementById('host_prompts').value.split("\n"),
+            }};
+        }
+        let req =JSON.stringify({ "HostStartGame": { "room_code": room_code.innerHTML, "rounds": rounds, "game": game }});
         console.log("Start game: " + req);
         ws.send(req);
 
     }
 
-    rock_btn.onclick = function() {
-        let req =JSON.stringify({ "PlayerHand": { "user_name": user_name.innerHTML, "room_code" : room_code.innerHTML, hand: "Rock" }});
-        console.log("Sending hand: " + req);
+    host_restart_btn.onclick = function () {
+        let req =JSON.stringify({ "HostRestartGame": { "room_code": room_code.innerHTML }});
+        console.log("Restart game: " + req);
         ws.send(req);
     }
 
-    paper_btn.onclick = function() {
-        let req =JSON.stringify({ "PlayerHand": { "user_name": user_name.innerHTML, "room_code" : room_code.innerHTML, hand: "Paper" }});
-        console.log("Sending hand: " + req);
+    host_end_btn.onclick = function () {
+        let req =JSON.stringify({ "HostEndGame": { "room_code": room_code.innerHTML }});
+        console.log("End game: " + req);
         ws.send(req);
     }
 
-    scissors_btn.onclick = function() {
-        let req =JSON.stringify({ "PlayerHand": { "user_name": user_name.innerHTML, "room_code" : room_code.innerHTML, hand: "Scissors" }});
+    throw_hand = function(hand) {
+        let req =JSON.stringify({ "GameAction": { "room_code" : room_code.innerHTML, "action": { "Rps": { "Throw": { "hand": hand }}}}});
         console.log("Sending hand: " + req);
         ws.send(req);
     }
 
+    answer_question = function(answer) {
+        let req =JSON.stringify({ "GameAction": { "room_code" : room_code.innerHTML, "action": { "Trivia": { "Answer": { "answer": answer }}}}});
+        console.log("Sending answer: " + req);
+        ws.send(req);
+    }
+
+    send_action = function(action) {
+        let req =JSON.stringify({ "GameAction": { "room_code" : room_code.innerHTML, "action": action }});
+        console.log("Sending action: " + req);
+        ws.send(req);
+    }
+
+    list_question_packs = function() {
+        ws.send(JSON.stringify("ListQuestionPacks"));
+    }
+
 }
 
 
@@ -82,27 +141,16 @@ join_game_mode.onclick = function() {
 }
 
 room_connect.onclick = function() {
+    close_login_socket();
     let ws = new WebSocket(uri);
-    let connect_btn = document.getElementById('room_connect');
+    login_ws = ws;
     let room_code_input = document.getElementById('room_code_input');
     let user_name_input = document.getElementById('player_name_input');
-    let room_login_div = document.getElementById('room_login');
-    let game_lobby_div = document.getElementById('connected_lobby');
-
-    let rock_btn = document.getElementById('rock_btn');
-    let paper_btn = document.getElementById('paper_btn');
-    let scissors_btn = document.getElementById('scissors_btn');
 
     ws.onopen = function() {
         ws_connect_status.innerHTML = '<p><em>Connected!</em></p>';
-        user_type = "Player";
+        user_type = document.getElementById('join_as_audience').checked ? "Audience" : "Player";
         join_game_lobby(ws, user_type, user_name_input.value, room_code_input.value );
-
-        connect_btn.remove();
-        room_login_div.remove();
-        game_lobby_div.style.display = "block";
-
-        display_username(user_name_input.value);
     };
 
     ws.onmessage = function(msg) {
@@ -111,27 +159,287 @@ room_connect.onclick = function() {
 
     ws.onclose = function() {
         ws_connect_status.getElementsByTagName('em')[0].innerText = 'Disconnected!';
+        resume_session(function(new_ws) { ws = new_ws; }, 0);
     };
 
-    rock_btn.onclick = function() {
-        let req =JSON.stringify({ "PlayerHand": { "user_name": user_name.innerHTML, "room_code" : room_code.innerHTML, hand: "Rock" }});
+    throw_hand = function(hand) {
+        let req =JSON.stringify({ "GameAction": { "room_code" : room_code.innerHTML, "action": { "Rps": { "Throw": { "hand": hand }}}}});
         console.log("Sending hand: " + req);
         ws.send(req);
     }
 
-    paper_btn.onclick = function() {
-        let req =JSON.stringify({ "PlayerHand": { "user_name": user_name.innerHTML, "room_code" : room_code.innerHTML, hand: "Paper" }});
-        console.log("Sending hand: " + req);
+    // The audience votes instead of throwing
+    vote_hand = function(hand) {
+        let req =JSON.stringify({ "GameAction": { "room_code" : room_code.innerHTML, "action": { "Rps": { "Vote": { "hand": hand }}}}});
+        console.log("Sending vote: " + req);
         ws.send(req);
     }
 
-    scissors_btn.onclick = function() {
-        let req =JSON.stringify({ "PlayerHand": { "user_name": user_name.innerHTML, "room_code" : room_code.innerHTML, hand: "Scissors" }});
-        console.log("Sending hand: " + req);
+    answer_question = function(answer) {
+        let req =JSON.stringify({ "GameAction": { "room_code" : room_code.innerHTML, "action": { "Trivia": { "Answer": { "answer": answer }}}}});
+        console.log("Sending answer: " + req);
         ws.send(req);
     }
+
+    send_action = function(action) {
+        let req =JSON.stringify({ "GameAction": { "room_code" : room_code.innerHTML, "action": action }});
+        console.log("Sending action: " + req);
+        ws.send(req);
+    }
+}
+
+
+// Reattach to our spot in the room after the socket drops
+function resume_session(on_reconnect, attempts) {
+    let token = sessionStorage.getItem("session_token");
+
+    if (!token || attempts >= 10) {
+        return;
+    }
+
+    setTimeout(function() {
+        let ws = new WebSocket(uri);
+
+        ws.onopen = function() {
+            ws_connect_status.innerHTML = '<p><em>Reconnected!</em></p>';
+
+            let req = JSON.stringify({ "Resume": { "token": token }});
+            console.log("Resuming session");
+            ws.send(req);
+        };
+
+        ws.onmessage = function(msg) {
+            receive_msg(msg.data);
+        };
+
+        ws.onclose = function() {
+            ws_connect_status.getElementsByTagName('em')[0].innerText = 'Disconnected!';
+            resume_session(on_reconnect, attempts + 1);
+        };
+
+        on_reconnect(ws);
+    }, 2000);
+}
+
+function close_login_socket() {
+    if (login_ws) {
+        login_ws.onclose = null;
+        login_ws.close();
+        login_ws = null;
+    }
 }
 
+// Why we got turned away from a room, in words for the player
+const join_rejections = {
+    "RoomFull": "That room is full.",
+    "GameAlreadyStarted": "That room is in the middle of a game. Try again when it's over.",
+    "UnknownRoom": "There's no room with that code.",
+};
+
+function show_error(error) {
+    let error_message = document.getElementById('error_message');
+
+    if (error.request == "UserLogin" && join_rejections[error.code]) {
+        error_message.innerText = join_rejections[error.code];
+    } else {
+        error_message.innerText = error.message;
+    }
+    error_message.style.display = "block";
+
+    // A stale session isn't coming back, so stop trying
+    if (error.code == "InvalidSession") {
+        sessionStorage.removeItem("session_token");
+    }
+
+    // Offer the server's pick when our name was turned down
+    if (error.suggested_name) {
+        let input_id = (error.request == "HostNewGame") ? 'host_user_name' : 'player_name_input';
+        document.getElementById(input_id).value = error.suggested_name;
+        error_message.innerText += " How about " + error.suggested_name + "?";
+    }
+
+    console.log("Request " + error.request + " failed with " + error.code + ": " + error.message);
+}
+
+// Bracket games don't have a set number of rounds
+function round_label(round, total_rounds) {
+    return total_rounds ? round + " / " + total_rounds : "" + round;
+}
+
+// Draw the current bracket round, with everyone's losses so far
+function show_bracket(update) {
+    let bracket = update.bracket;
+    let matches = document.getElementById('bracket_matches');
+
+    document.getElementById('bracket').style.display = "block";
+    document.getElementById('bracket_round').innerText = bracket.round;
+
+    matches.innerHTML = "";
+    bracket.matches.forEach(function(m) {
+        let item = document.createElement('li');
+        item.innerText = m.players[0] + " vs " + m.players[1]
+            + (m.winner ? ": " + m.winner + " wins" : "")
+            + (m.draws ? " (" + m.draws + " draws)" : "");
+        matches.appendChild(item);
+    });
+    bracket.byes.forEach(function(bye) {
+        let item = document.createElement('li');
+        item.innerText = bye + " has a bye";
+        matches.appendChild(item);
+    });
+
+    document.getElementById('bracket_out').innerText = bracket.entrants
+        .filter(function(e) { return e.eliminated_in !== null; })
+        .map(function(e) { return e.user_name; })
+        .join(", ");
+}
+
+// Count down to the end of the round, or just show the round when it isn't timed
+function start_round_timer(round) {
+    let timer = document.getElementById('round_timer');
+
+    clearInterval(round_timer);
+    round_timer = null;
+
+    if (round.seconds_left === null) {
+        timer.innerText = "Round " + round_label(round.round, round.total_rounds);
+        return;
+    }
+
+    let seconds_left = round.seconds_left;
+    let tick = function() {
+        timer.innerText = "Round " + round_label(round.round, round.total_rounds) + ": "
+            + seconds_left + "s left";
+
+        if (seconds_left <= 0) {
+            clearInterval(round_timer);
+            round_timer = null;
+        }
+        seconds_left -= 1;
+    };
+
+    tick();
+    round_timer = setInterval(tick, 1000);
+}
+
+// One button per move in the room's rule-set, for throwing and for voting
+function show_hand_buttons(rule_set) {
+    [["hand_buttons", function(hand) { throw_hand(hand); }],
+     ["vote_buttons", function(hand) { vote_hand(hand); }]].forEach(function(buttons) {
+        let container = document.getElementById(buttons[0]);
+
+        container.innerHTML = "";
+        rule_set.moves.forEach(function(move, i) {
+            let btn = document.createElement('button');
+            btn.type = "button";
+            btn.innerText = move;
+            btn.title = "Beats " + rule_set.beats[i].map(function(j) { return rule_set.moves[j]; }).join(", ");
+            btn.onclick = function() { buttons[1](move); };
+            container.appendChild(btn);
+        });
+    });
+}
+
+// One button per answer, lettered the same way chat answers them
+function show_answer_buttons(answers) {
+    let container = document.getElementById('answer_buttons');
+
+    container.innerHTML = "";
+    answers.forEach(function(answer, i) {
+        let btn = document.createElement('button');
+        btn.type = "button";
+        btn.innerText = String.fromCharCode(65 + i) + ": " + answer;
+        btn.onclick = function() { answer_question(i); };
+        container.appendChild(btn);
+    });
+}
+
+// Drawings are sent in canvas units, whatever size the canvas is shown at
+const CANVAS_SIZE = 500;
+let prompt_strokes = [];
+let prompt_pen_down = false;
+
+function canvas_point(canvas, e) {
+    let rect = canvas.getBoundingClientRect();
+    return [Math.round((e.clientX - rect.left) * CANVAS_SIZE / rect.width),
+            Math.round((e.clientY - rect.top) * CANVAS_SIZE / rect.height)];
+}
+
+function draw_strokes(canvas, strokes) {
+    let ctx = canvas.getContext('2d');
+    let scale = canvas.width / CANVAS_SIZE;
+
+    ctx.clearRect(0, 0, canvas.width, canvas.height);
+    ctx.lineCap = "round";
+    ctx.lineJoin = "round";
+    strokes.forEach(function(stroke) {
+        ctx.strokeStyle = stroke.color;
+        ctx.lineWidth = stroke.width * scale;
+        ctx.beginPath();
+        stroke.points.forEach(function(p, i) {
+            if (i == 0) {
+                ctx.moveTo(p[0] * scale, p[1] * scale);
+            } else {
+                ctx.lineTo(p[0] * scale, p[1] * scale);
+            }
+        });
+        // A single point is still a dot
+        if (stroke.points.length == 1) {
+            ctx.lineTo(stroke.points[0][0] * scale + 0.1, stroke.points[0][1] * scale);
+        }
+        ctx.stroke();
+    });
+}
+
+let prompt_canvas = document.getElementById('prompt_canvas');
+prompt_canvas.onpointerdown = function(e) {
+    prompt_pen_down = true;
+    prompt_strokes.push({
+        "color": document.getElementById('prompt_pen_color').value,
+        "width": 4,
+        "points": [canvas_point(prompt_canvas, e)],
+    });
+    draw_strokes(prompt_canvas, prompt_strokes);
+};
+prompt_canvas.onpointermove = function(e) {
+    if (prompt_pen_down) {
+        prompt_strokes[prompt_strokes.length - 1].points.push(canvas_point(prompt_canvas, e));
+        draw_strokes(prompt_canvas, prompt_strokes);
+    }
+};
+prompt_canvas.onpointerup = prompt_canvas.onpointerleave = function() {
+    prompt_pen_down = false;
+};
+document.getElementById('prompt_canvas_clear').onclick = function() {
+    prompt_strokes = [];
+    draw_strokes(prompt_canvas, prompt_strokes);
+};
+
+// Send whatever's been drawn, or failing that, written
+document.getElementById('prompt_submit_btn').onclick = function() {
+    let text = document.getElementById('prompt_text_input').value;
+    let submission = (prompt_strokes.length && (document.getElementById('prompt_drawing_submit').style.display != "none"))
+        ? { "Drawing": prompt_strokes }
+        : { "Text": text };
+
+    send_action({ "Prompt": { "Submit": { "submission": submission }}});
+};
+
+// Show an entry as text or as its drawing
+function show_submission(container, submission) {
+    if (submission["Text"] !== undefined) {
+        let text = document.createElement('span');
+        text.innerText = submission["Text"];
+        container.appendChild(text);
+    } else {
+        let canvas = document.createElement('canvas');
+        canvas.width = 150;
+        canvas.height = 150;
+        canvas.style.border = "1px solid black";
+        draw_strokes(canvas, submission["Drawing"]);
+        container.appendChild(canvas);
+    }
+}
 
 function display_username(name) {
     let user = document.getElementById('user_name');
@@ -143,14 +451,42 @@ function receive_msg(data) {
 
     let parsed = JSON.parse(data);
 
-    if (parsed["PartyUpdate"]) {
+    // Game events come wrapped with the game they're from, which the page
+    // doesn't need to know
+    if (parsed["GameEvent"]) {
+        let event = parsed["GameEvent"].event;
+        parsed = event[Object.keys(event)[0]];
+    }
+
+    if (parsed["SessionStarted"]) {
+        let session = parsed["SessionStarted"];
+
+        // Hang on to the token so a dropped socket can pick up where it left off
+        sessionStorage.setItem("session_token", session.session_token);
+        user_type = session.user_type;
+
+        room_code.innerHTML = session.room_code;
+        display_username(session.user_name);
+        show_hand_buttons(session.rule_set);
+
+        // Logged in, so the login forms can go away
+        login_ws = null;
+        ["landing", "host_login", "room_login"].forEach(function(id) {
+            document.getElementById(id).style.display = "none";
+        });
+        document.getElementById('error_message').style.display = "none";
+        document.getElementById('connected_lobby').style.display = "block";
+    } else if (parsed["PartyUpdate"]) {
 
         let room_code = document.getElementById('room_code');
         let party_members = document.getElementById('party_members');
-        let host_start_btn = document.getElementById('host_start_game');
+        let host_start_btn = document.getElementById('host_start_controls');
 
         room_code.innerHTML = parsed["PartyUpdate"].room_code;
         party_members.innerHTML = parsed["PartyUpdate"].users;
+        document.getElementById('audience_count').innerText = parsed["PartyUpdate"].audience_count;
+        document.getElementById('party_size').innerText = parsed["PartyUpdate"].users.length
+            + " / " + parsed["PartyUpdate"].max_players;
 
 
         // If party size > 1, the host start button should appear
@@ -165,10 +501,22 @@ function receive_msg(data) {
 
     } else if (parsed["GameStart"]) {
         let game_controls = document.getElementById('active_game_controls');
-        let host_start_btn = document.getElementById('host_start_game');
+        let host_start_btn = document.getElementById('host_start_controls');
 
-        // Display RPS controls
+        let trivia = !!parsed["GameStart"].settings["Trivia"];
+        let prompt = !!parsed["GameStart"].settings["Prompt"];
+
+        // Display the game's controls, the audience only gets to watch
         game_controls.style.display = "block";
+        document.getElementById('hand_buttons').style.display = (user_type == "Audience" || trivia || prompt) ? "none" : "inline";
+        document.getElementById('trivia_question').style.display = trivia ? "block" : "none";
+        document.getElementById('prompt_round').style.display = prompt ? "block" : "none";
+        document.getElementById('audience_vote').style.display = "none";
+        document.getElementById('round_opponent').innerText = "";
+        document.getElementById('game_over').style.display = "none";
+        document.getElementById('bracket').style.display = "none";
+        document.getElementById('round_results').innerHTML = "";
+        document.getElementById('audience_vote_results').innerHTML = "";
 
         // And the host's start game button should go away
         if (user_type == "Host") {
@@ -176,10 +524,281 @@ function receive_msg(data) {
         }
 
         console.log("Host has started the game");
-    } else if (parsed["ServerHand"]) {
+    } else if (parsed["RoundStarted"]) {
+        let round = parsed["RoundStarted"];
+        let matchup = round.matchups.find(function(m) { return m.user_name == user_name.innerHTML; });
+
+        start_round_timer(round);
+
+        if (matchup) {
+            document.getElementById('round_opponent').innerText = "You're up against "
+                + (matchup.opponent || "the server");
+        } else {
+            document.getElementById('round_opponent').innerText = "";
+        }
+    } else if (parsed["QuestionStarted"]) {
+        let question = parsed["QuestionStarted"];
+
+        start_round_timer(question);
+        document.getElementById('trivia_question_text').innerText = question.question;
+        show_answer_buttons(question.answers);
+        document.getElementById('answer_buttons').style.display = (user_type == "Audience") ? "none" : "inline";
+    } else if (parsed["AnswerLocked"]) {
+        let locked = parsed["AnswerLocked"];
+        let line = document.createElement('p');
+
+        line.innerText = locked.user_name + " has answered";
+        document.getElementById('round_results').prepend(line);
+
+        // Answers are final, so stop taking more
+        if (locked.user_name == user_name.innerHTML) {
+            document.getElementById('answer_buttons').style.display = "none";
+        }
+    } else if (parsed["QuestionResult"]) {
+        let result = parsed["QuestionResult"];
+        let round_results = document.getElementById('round_results');
+
+        clearInterval(round_timer);
+        round_timer = null;
+        document.getElementById('answer_buttons').style.display = "none";
+
+        let shares = result.answers.map(function(share, i) {
+            return String.fromCharCode(65 + i) + " " + Math.round(share.percent) + "%";
+        });
+        let right = result.results
+            .filter(function(r) { return r.correct; })
+            .map(function(r) { return r.user_name + " +" + r.points; });
+
+        round_results.innerHTML = "";
+        let line = document.createElement('p');
+        line.innerText = "The answer was " + String.fromCharCode(65 + result.correct) + ": "
+            + result.answers[result.correct].answer + ". Answers: " + shares.join(", ")
+            + ". Right: " + (right.length ? right.join(", ") : "nobody");
+        round_results.prepend(line);
+
+        console.log("Question result: " + JSON.stringify(result));
+    } else if (parsed["PromptStarted"]) {
+        let round = parsed["PromptStarted"];
+
+        start_round_timer(round);
+        document.getElementById('prompt_text').innerText = round.prompt;
+        document.getElementById('prompt_entries').innerHTML = "";
+        document.getElementById('round_results').innerHTML = "";
+
+        // Fresh page for every prompt
+        document.getElementById('prompt_text_input').value = "";
+        prompt_strokes = [];
+        draw_strokes(prompt_canvas, prompt_strokes);
+
+        document.getElementById('prompt_text_submit').style.display = (round.submissions == "Drawing") ? "none" : "inline";
+        document.getElementById('prompt_drawing_submit').style.display = (round.submissions == "Text") ? "none" : "inline";
+        document.getElementById('prompt_submit').style.display = (user_type == "Audience") ? "none" : "block";
+    } else if (parsed["SubmissionReceived"]) {
+        let received = parsed["SubmissionReceived"];
+        let line = document.createElement('p');
+
+        line.innerText = received.user_name + " has submitted";
+        document.getElementById('round_results').prepend(line);
+
+        if (received.user_name == user_name.innerHTML) {
+            document.getElementById('prompt_submit').style.display = "none";
+        }
+    } else if (parsed["VotingOpen"]) {
+        let voting = parsed["VotingOpen"];
+        let entries = document.getElementById('prompt_entries');
+
+        start_round_timer(voting);
+        document.getElementById('prompt_submit').style.display = "none";
+
+        // Entries are anonymous, so the only way to tell them apart is their number
+        entries.innerHTML = "Vote for your favourite:";
+        voting.entries.forEach(function(entry) {
+            let item = document.createElement('div');
+            item.id = "prompt_entry_" + entry.id;
+            item.appendChild(document.createTextNode((entry.id + 1) + ") "));
+            show_submission(item, entry.submission);
+
+            let vote = document.createElement('button');
+            vote.type = "button";
+            vote.innerText = "Vote";
+            vote.onclick = function() { send_action({ "Prompt": { "Vote": { "entry": entry.id }}}); };
+            item.appendChild(vote);
+
+            // The host keeps anything unfit for stream off it
+            if (user_type == "Host") {
+                let remove = document.createElement('button');
+                remove.type = "button";
+                remove.innerText = "Take down";
+                remove.onclick = function() { send_action({ "Prompt": { "Remove": { "entry": entry.id }}}); };
+                item.appendChild(remove);
+            }
 
-        console.log("Server threw hand: " + parsed["ServerHand"].hand);
+            entries.appendChild(item);
+        });
+    } else if (parsed["EntryRemoved"]) {
+        let entry = document.getElementById('prompt_entry_' + parsed["EntryRemoved"].entry);
+        if (entry) {
+            entry.remove();
+        }
+    } else if (parsed["RoundResults"]) {
+        let results = parsed["RoundResults"];
+        let entries = document.getElementById('prompt_entries');
+
+        clearInterval(round_timer);
+        round_timer = null;
+
+        entries.innerHTML = "Results:";
+        results.results.forEach(function(result) {
+            let item = document.createElement('div');
+            item.appendChild(document.createTextNode(result.user_name + ": "));
+            show_submission(item, result.submission);
+            item.appendChild(document.createTextNode(" " + result.votes + " votes, +" + result.points));
+            entries.appendChild(item);
+        });
+
+        console.log("Round results: " + JSON.stringify(results));
+    } else if (parsed["QuestionPacks"]) {
+        let select = document.getElementById('host_question_pack');
+
+        select.innerHTML = "";
+        parsed["QuestionPacks"].packs.forEach(function(pack) {
+            let option = document.createElement('option');
+            option.value = pack.name;
+            option.innerText = (pack.title || pack.name) + " (" + pack.questions + " questions)";
+            select.appendChild(option);
+        });
+    } else if (parsed["BracketUpdate"]) {
+        show_bracket(parsed["BracketUpdate"]);
+    } else if (parsed["HandLocked"]) {
+        let locked = parsed["HandLocked"];
+        let line = document.createElement('p');
+
+        line.innerText = locked.user_name + " has thrown, waiting on their opponent";
+        document.getElementById('round_results').prepend(line);
+    } else if (parsed["ThrowMissed"]) {
+        let missed = parsed["ThrowMissed"];
+        let line = document.createElement('p');
+
+        line.innerText = missed.user_name + " ran out of time in round " + missed.round;
+        document.getElementById('round_results').prepend(line);
+    } else if (parsed["RoundResult"]) {
+        let result = parsed["RoundResult"];
+        let round_results = document.getElementById('round_results');
+
+        let line = document.createElement('p');
+        let opponent = result.opponent ? result.opponent + "'s " : "the server's ";
+        line.innerText = result.user_name + " threw " + result.player_hand
+            + " vs " + opponent + result.server_hand + ": " + result.outcome;
+        round_results.prepend(line);
+
+        console.log("Round result: " + JSON.stringify(result));
+    } else if (parsed["AudienceVoteOpen"]) {
+        let vote = parsed["AudienceVoteOpen"];
+        let prompt = document.getElementById('audience_vote_prompt');
+
+        if (vote.mode == "Predict") {
+            prompt.innerText = "Round " + vote.round + ": what will " + vote.spotlight + " throw?";
+        } else {
+            prompt.innerText = "Round " + vote.round + ": pick the server's hand!";
+        }
 
+        // Only the audience gets a vote
+        document.getElementById('audience_vote').style.display = (user_type == "Audience") ? "block" : "none";
+    } else if (parsed["AudienceVoteResult"]) {
+        let result = parsed["AudienceVoteResult"];
+        let vote_results = document.getElementById('audience_vote_results');
+
+        let shares = result.votes.map(function(share) {
+            return share.hand + " " + Math.round(share.percent) + "%";
+        });
+
+        let line = document.createElement('p');
+        if (result.mode == "Predict") {
+            line.innerText = "Audience predicted " + shares.join(", ") + " for " + result.user_name
+                + ", who threw " + result.hand;
+        } else {
+            line.innerText = "Audience voted " + shares.join(", ") + " and threw " + result.hand
+                + " at " + result.user_name;
+        }
+        vote_results.prepend(line);
+
+        console.log("Audience vote: " + JSON.stringify(result));
+    } else if (parsed["ScoreUpdate"]) {
+        let update = parsed["ScoreUpdate"];
+        let scoreboard = document.getElementById('scoreboard');
+        let table = document.getElementById('scoreboard_table');
+
+        scoreboard.style.display = "block";
+        document.getElementById('scoreboard_round').innerText = round_label(update.round, update.total_rounds);
+
+        table.innerHTML = "<tr><th>Player</th><th>Played</th><th>W</th><th>L</th><th>D</th><th>Points</th></tr>";
+        update.scores.forEach(function(entry) {
+            let row = table.insertRow();
+            [entry.user_name, entry.rounds_played, entry.score.wins, entry.score.losses, entry.score.draws, entry.score.points].forEach(function(value) {
+                row.insertCell().innerText = value;
+            });
+        });
+    } else if (parsed["GameOver"]) {
+        let game_over = parsed["GameOver"];
+        let standings = document.getElementById('game_over_standings');
+
+        clearInterval(round_timer);
+        round_timer = null;
+
+        document.getElementById('active_game_controls').style.display = "none";
+        document.getElementById('game_over').style.display = "block";
+        document.getElementById('game_over_winners').innerText = game_over.winners.join(", ");
+
+        // The host decides if everyone plays again
+        if (user_type == "Host") {
+            document.getElementById('host_end_controls').style.display = "block";
+        }
+
+        standings.innerHTML = "";
+        game_over.standings.forEach(function(standing) {
+            let item = document.createElement('li');
+            item.innerText = "#" + standing.rank + " " + standing.user_name + " ("
+                + (standing.score.points ? standing.score.points + " points, " : "")
+                + standing.score.wins + "W " + standing.score.losses + "L " + standing.score.draws + "D)";
+            standings.appendChild(item);
+        });
+
+        console.log("Game over: " + JSON.stringify(game_over));
+    } else if (parsed["ServerShuttingDown"]) {
+        let error_message = document.getElementById('error_message');
+
+        error_message.innerText = "The server is shutting down in " + parsed["ServerShuttingDown"].seconds
+            + " seconds.";
+        error_message.style.display = "block";
+
+        // Rooms don't survive a restart, so there won't be anything to resume
+        sessionStorage.removeItem("session_token");
+    } else if (parsed["RoomExpiring"]) {
+        let error_message = document.getElementById('error_message');
+
+        error_message.innerText = "This room is closing in " + parsed["RoomExpiring"].seconds_left
+            + " seconds unless someone plays.";
+        error_message.style.display = "block";
+    } else if (parsed["HostAway"]) {
+        let error_message = document.getElementById('error_message');
+
+        error_message.innerText = "The host lost their connection. This room is closing in "
+            + parsed["HostAway"].seconds_left + " seconds unless they come back.";
+        error_message.style.display = "block";
+    } else if (parsed["HostBack"]) {
+        document.getElementById('error_message').style.display = "none";
+    } else if (parsed["RoomClosed"]) {
+        ["connected_lobby", "active_game_controls", "scoreboard", "game_over"].forEach(function(id) {
+            document.getElementById(id).style.display = "none";
+        });
+        document.getElementById('room_closed').style.display = "block";
+
+        // Nothing left to reconnect to
+        sessionStorage.removeItem("session_token");
+
+        console.log("Room closed: " + parsed["RoomClosed"].reason);
+    } else if (parsed["Error"]) {
+        show_error(parsed["Error"]);
     } else {
         console.log("key check failed: " + data);
         console.log(typeof data);
@@ -202,10 +821,26 @@ function join_game_lobby(ws, user_type, user_name, room_code) {
 
 
     if (user_type == "Host") {
+        let max_players = parseInt(document.getElementById('host_max_players').value, 10);
+        if (max_players > 0) {
+            login_info["max_players"] = max_players;
+        }
+        login_info["allow_late_join"] = document.getElementById('host_allow_late_join').checked;
+
+        let rule_set = document.getElementById('host_rule_set').value;
+        if (rule_set == "Custom") {
+            let moves = document.getElementById('host_custom_moves').value.split(",")
+                .map(function(move) { return move.trim(); })
+                .filter(function(move) { return move.length > 0; });
+            login_info["rule_set"] = { "Custom": { "moves": moves } };
+        } else {
+            login_info["rule_set"] = rule_set;
+        }
+
         let req = JSON.stringify({ "HostNewGame" : login_info });
         ws.send(req);
         console.log("Sending" + req);
-    } else if ((user_type == "Player")) {
+    } else if ((user_type == "Player") || (user_type == "Audience")) {
         let req = JSON.stringify({ "UserLogin" : login_info });
         ws.send(req);
         console.log("Sending" + req);
@@ -0,0 +1,244 @@
+// Read-only view of a room for stream overlays. Nothing is ever sent back.
+const overlay_room = new URLSearchParams(location.search).get("room") || "";
+const overlay_uri = 'ws://' + location.host + '/overlay/' + encodeURIComponent(overlay_room);
+
+// Most round results to keep on screen
+const MAX_RESULTS = 5;
+// Drawings are sent in canvas units, whatever size they're shown at
+const CANVAS_SIZE = 500;
+
+let overlay_status = document.getElementById('overlay_status');
+let overlay_timer = null;
+
+function overlay_connect() {
+    let ws = new WebSocket(overlay_uri);
+
+    ws.onmessage = function(msg) {
+        receive_overlay_msg(msg.data);
+    };
+
+    // OBS keeps the page open between streams, so keep trying
+    ws.onclose = function() {
+        setTimeout(overlay_connect, 5000);
+    };
+}
+
+function start_overlay_timer(seconds_left) {
+    let timer = document.getElementById('overlay_timer');
+
+    clearInterval(overlay_timer);
+    timer.innerText = "";
+
+    if (seconds_left === null) {
+        return;
+    }
+
+    let tick = function() {
+        timer.innerText = seconds_left + "s";
+        if (seconds_left <= 0) {
+            clearInterval(overlay_timer);
+        }
+        seconds_left -= 1;
+    };
+
+    tick();
+    overlay_timer = setInterval(tick, 1000);
+}
+
+function show_bracket(bracket) {
+    let matches = document.getElementById('overlay_bracket_matches');
+
+    document.getElementById('overlay_bracket').style.display = "block";
+    document.getElementById('overlay_bracket_round').innerText = bracket.round;
+
+    matches.innerHTML = "";
+    bracket.matches.forEach(function(m) {
+        let row = matches.insertRow();
+        m.players.forEach(function(player) {
+            let cell = row.insertCell();
+            cell.innerText = player;
+            if (m.winner === player) {
+                cell.style.fontWeight = "bold";
+            } else if (m.winner) {
+                cell.style.textDecoration = "line-through";
+            }
+        });
+    });
+    bracket.byes.forEach(function(bye) {
+        matches.insertRow().insertCell().innerText = bye + " (bye)";
+    });
+}
+
+// Show a prompt game entry as text or as its drawing
+function show_entry(label, submission) {
+    let item = document.createElement('div');
+
+    item.appendChild(document.createTextNode(label));
+    if (submission["Text"] !== undefined) {
+        item.appendChild(document.createTextNode(submission["Text"]));
+    } else {
+        let canvas = document.createElement('canvas');
+        let ctx = canvas.getContext('2d');
+        canvas.width = canvas.height = 120;
+        canvas.style.background = "white";
+
+        let scale = canvas.width / CANVAS_SIZE;
+        ctx.lineCap = ctx.lineJoin = "round";
+        submission["Drawing"].forEach(function(stroke) {
+            ctx.strokeStyle = stroke.color;
+            ctx.lineWidth = stroke.width * scale;
+            ctx.beginPath();
+            stroke.points.forEach(function(p, i) {
+                if (i == 0) {
+                    ctx.moveTo(p[0] * scale, p[1] * scale);
+                } else {
+                    ctx.lineTo(p[0] * scale, p[1] * scale);
+                }
+            });
+            ctx.stroke();
+        });
+        item.appendChild(canvas);
+    }
+
+    document.getElementById('overlay_entries').appendChild(item);
+    return item;
+}
+
+function show_result(text) {
+    let results = document.getElementById('overlay_results');
+
+    let line = document.createElement('p');
+    line.innerText = text;
+    results.prepend(line);
+
+    while (results.childElementCount > MAX_RESULTS) {
+        results.removeChild(results.lastChild);
+    }
+}
+
+function receive_overlay_msg(data) {
+    let parsed = JSON.parse(data);
+
+    // Game events come wrapped with the game they're from, which the page
+    // doesn't need to know
+    if (parsed["GameEvent"]) {
+        let event = parsed["GameEvent"].event;
+        parsed = event[Object.keys(event)[0]];
+    }
+
+    if (parsed["PartyUpdate"]) {
+        let party = parsed["PartyUpdate"];
+
+        overlay_status.innerText = "";
+        document.getElementById('overlay_room_code').innerText = party.room_code;
+        document.getElementById('overlay_party_members').innerText = party.users.join(", ")
+            + " (+" + party.audience_count + " watching)";
+    } else if (parsed["GameStart"]) {
+        document.getElementById('overlay_round').style.display = "block";
+        document.getElementById('overlay_game_over').style.display = "none";
+        document.getElementById('overlay_results').innerHTML = "";
+        document.getElementById('overlay_bracket').style.display = "none";
+        document.getElementById('overlay_votes').innerText = "";
+        document.getElementById('overlay_question').innerText = "";
+        document.getElementById('overlay_entries').innerHTML = "";
+    } else if (parsed["ScoreUpdate"]) {
+        let update = parsed["ScoreUpdate"];
+        let table = document.getElementById('overlay_scoreboard');
+
+        document.getElementById('overlay_round_number').innerText = update.total_rounds
+            ? update.round + " / " + update.total_rounds
+            : update.round;
+
+        table.innerHTML = "<tr><th>Player</th><th>W</th><th>L</th><th>D</th><th>Points</th></tr>";
+        update.scores.forEach(function(entry) {
+            let row = table.insertRow();
+            [entry.user_name, entry.score.wins, entry.score.losses, entry.score.draws, entry.score.points].forEach(function(value) {
+                row.insertCell().innerText = value;
+            });
+        });
+    } else if (parsed["RoundStarted"]) {
+        start_overlay_timer(parsed["RoundStarted"].seconds_left);
+    } else if (parsed["QuestionStarted"]) {
+        let question = parsed["QuestionStarted"];
+
+        start_overlay_timer(question.seconds_left);
+        document.getElementById('overlay_question').innerText = question.question + " "
+            + question.answers.map(function(answer, i) {
+                return String.fromCharCode(65 + i) + ") " + answer;
+            }).join("  ");
+    } else if (parsed["QuestionResult"]) {
+        let result = parsed["QuestionResult"];
+
+        start_overlay_timer(null);
+        document.getElementById('overlay_question').innerText = "";
+        document.getElementById('overlay_votes').innerText = "Answers: " + result.answers.map(function(share, i) {
+            return String.fromCharCode(65 + i) + " " + Math.round(share.percent) + "%";
+        }).join(", ");
+        show_result("The answer was " + result.answers[result.correct].answer);
+    } else if (parsed["PromptStarted"]) {
+        let round = parsed["PromptStarted"];
+
+        start_overlay_timer(round.seconds_left);
+        document.getElementById('overlay_question').innerText = round.prompt;
+        document.getElementById('overlay_entries').innerHTML = "";
+    } else if (parsed["SubmissionReceived"]) {
+        show_result(parsed["SubmissionReceived"].user_name + " has submitted");
+    } else if (parsed["VotingOpen"]) {
+        let voting = parsed["VotingOpen"];
+
+        start_overlay_timer(voting.seconds_left);
+        document.getElementById('overlay_results').innerHTML = "";
+        voting.entries.forEach(function(entry) {
+            show_entry((entry.id + 1) + ") ", entry.submission).id = "overlay_entry_" + entry.id;
+        });
+    } else if (parsed["EntryRemoved"]) {
+        let entry = document.getElementById('overlay_entry_' + parsed["EntryRemoved"].entry);
+        if (entry) {
+            entry.remove();
+        }
+    } else if (parsed["RoundResults"]) {
+        let results = parsed["RoundResults"];
+
+        start_overlay_timer(null);
+        document.getElementById('overlay_entries').innerHTML = "";
+        results.results.forEach(function(result) {
+            show_entry(result.user_name + " (" + result.votes + " votes): ", result.submission);
+        });
+    } else if (parsed["ThrowMissed"]) {
+        show_result(parsed["ThrowMissed"].user_name + " ran out of time");
+    } else if (parsed["RoundResult"]) {
+        let result = parsed["RoundResult"];
+
+        if (!result.opponent) {
+            show_result(result.user_name + " threw " + result.player_hand + " vs " + result.server_hand
+                + ": " + result.outcome);
+        } else if (result.user_name < result.opponent) {
+            // Both players in a pair get a result, only show one of them
+            show_result(result.user_name + " threw " + result.player_hand + " vs " + result.opponent
+                + "'s " + result.server_hand + ": " + result.outcome);
+        }
+    } else if (parsed["BracketUpdate"]) {
+        show_bracket(parsed["BracketUpdate"].bracket);
+    } else if (parsed["AudienceVoteResult"]) {
+        let result = parsed["AudienceVoteResult"];
+
+        document.getElementById('overlay_votes').innerText = "Audience: " + result.votes.map(function(share) {
+            return share.hand + " " + Math.round(share.percent) + "%";
+        }).join(", ");
+    } else if (parsed["GameOver"]) {
+        start_overlay_timer(null);
+        document.getElementById('overlay_game_over').style.display = "block";
+        document.getElementById('overlay_winners').innerText = parsed["GameOver"].winners.join(", ");
+    } else if (parsed["RoomClosed"]) {
+        overlay_status.innerText = "Room closed";
+        document.getElementById('overlay_round').style.display = "none";
+    } else if (parsed["Error"]) {
+        overlay_status.innerText = parsed["Error"].message;
+    }
+}
+
+if (overlay_room) {
+    overlay_connect();
+} else {
+    overlay_status.innerText = "Add ?room=CODE to the overlay's address";
+}